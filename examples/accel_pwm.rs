@@ -132,12 +132,6 @@ static LED_RAMP: [u16; 256] = [
 ];
 
 fn led_ramp(v: f32) -> u16 {
-    let v = if v < 0.0 {
-        0.0
-    } else if v > 1.0 {
-        1.0
-    } else {
-        v
-    };
+    let v = v.clamp(0.0, 1.0);
     LED_RAMP[(v * 255.0) as usize]
 }
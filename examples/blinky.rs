@@ -9,6 +9,7 @@ use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
 use stm32f3_discovery::stm32f3xx_hal::prelude::*;
 use stm32f3_discovery::stm32f3xx_hal::pac;
 
+use stm32f3_discovery::clocks::ClockProfile;
 use stm32f3_discovery::leds::Leds;
 use stm32f3_discovery::switch_hal::{OutputSwitch, ToggleableOutputSwitch};
 
@@ -19,7 +20,7 @@ fn main() -> ! {
 
     let core_periphs = cortex_m::Peripherals::take().unwrap();
     let mut flash = device_periphs.FLASH.constrain();
-    let clocks = reset_and_clock_control.cfgr.freeze(&mut flash.acr);
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
     let mut delay = Delay::new(core_periphs.SYST, clocks);
 
     // initialize user leds
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::button::capture::{ButtonCapture, CaptureEvent};
+use stm32f3_discovery::button::UserButton;
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+
+    // setup ITM output
+    let stim = &mut core_periphs.ITM.stim[0];
+
+    // initialize user button and hand it over to TIM2
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+    let mut capture = ButtonCapture::new(
+        device_periphs.TIM2,
+        button,
+        &mut gpioa.moder,
+        &mut gpioa.otyper,
+        &mut gpioa.afrl,
+        clocks,
+        &mut reset_and_clock_control.apb1,
+    )
+    .map_err(|(error, _tim2, _button)| error)
+    .unwrap();
+
+    loop {
+        match capture.read() {
+            Some(CaptureEvent::Pressed { at_us }) => {
+                iprintln!(stim, "Pressed at {} us", at_us);
+            }
+            Some(CaptureEvent::Released { at_us, duration_us: Some(duration_us) }) => {
+                iprintln!(stim, "Released at {} us, held for {} us", at_us, duration_us);
+            }
+            _ => {}
+        }
+
+        if capture.overcaptured() {
+            iprintln!(stim, "Missed an edge");
+        }
+    }
+}
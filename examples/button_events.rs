@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::button::events::{ButtonEvent, ButtonEvents};
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::leds::{Color, Leds};
+use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let mut delay = Delay::new(core_periphs.SYST, clocks);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+    let mut events = ButtonEvents::new(button);
+
+    // poll every 10ms, using the number of ticks as our timestamp
+    let tick_ms = 10u32;
+    let mut now = 0u32;
+
+    loop {
+        delay.delay_ms(tick_ms);
+        now = now.wrapping_add(tick_ms);
+
+        let color = match events.update(now) {
+            Ok(Some(ButtonEvent::Click)) => Color::Green,
+            Ok(Some(ButtonEvent::DoubleClick)) => Color::Blue,
+            Ok(Some(ButtonEvent::LongPress)) => Color::Red,
+            Ok(Some(ButtonEvent::Held(_))) => Color::Orange,
+            _ => continue,
+        };
+
+        for led in &mut leds.for_color(color) {
+            led.toggle().ok();
+        }
+    }
+}
@@ -10,21 +10,32 @@ use stm32f3_discovery::stm32f3xx_hal::prelude::*;
 use stm32f3_discovery::stm32f3xx_hal::pac;
 use stm32f3_discovery::wait_for_interrupt;
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
 use stm32f3_discovery::button;
-use stm32f3_discovery::button::interrupt::TriggerMode;
+use stm32f3_discovery::button::interrupt::{ButtonInterrupt, TriggerMode};
+use stm32f3_discovery::exti::ExtiManager;
+use stm32f3_discovery::queue::{BoardEvent, EventQueue, Producer};
 
 use stm32f3_discovery::leds::Leds;
 use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
 
-static USER_BUTTON_PRESSED: AtomicBool = AtomicBool::new(false);
+const QUEUE_SIZE: usize = 8;
+static EVENTS: EventQueue<QUEUE_SIZE> = EventQueue::new();
+static BUTTON_INTERRUPT: Mutex<RefCell<Option<(ButtonInterrupt, Producer<'static, QUEUE_SIZE>)>>> =
+    Mutex::new(RefCell::new(None));
 
 #[interrupt]
 fn EXTI0() {
-    //If we don't clear the interrupt to signal it's been serviced, it will continue to fire.
-    button::interrupt::clear();
-    // pa0 has a low pass filter on it, so no need to debounce in software
-    USER_BUTTON_PRESSED.store(true, Ordering::Relaxed);
+    free(|cs| {
+        if let Some((button_int, events)) = BUTTON_INTERRUPT.borrow(cs).borrow_mut().as_mut() {
+            //If we don't clear the interrupt to signal it's been serviced, it will continue to fire.
+            button_int.clear();
+            // pa0 has a low pass filter on it, so no need to debounce in software
+            // If the main loop falls behind and the queue fills up, we drop the newest press.
+            events.push(BoardEvent::ButtonPressed).ok();
+        }
+    });
 }
 
 #[entry]
@@ -48,16 +59,18 @@ fn main() -> ! {
     );
     let mut status_led = leds.ld3;
 
-    button::interrupt::enable(
-        &device_periphs.EXTI,
-        &device_periphs.SYSCFG,
-        TriggerMode::Rising,
-    );
+    let syscfg = device_periphs.SYSCFG.constrain(&mut reset_and_clock_control.apb2);
+    let mut exti = ExtiManager::new(device_periphs.EXTI, syscfg);
+    let button_int = button::interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
+    let (producer, mut consumer) = EVENTS.split().unwrap();
+    free(|cs| BUTTON_INTERRUPT.borrow(cs).replace(Some((button_int, producer))));
 
     loop {
-        // check to see if flag was active and clear it
-        if USER_BUTTON_PRESSED.swap(false, Ordering::AcqRel) {
-            status_led.toggle().ok();
+        // handle every press that happened since we last woke up
+        while let Some(event) = consumer.pop() {
+            if event == BoardEvent::ButtonPressed {
+                status_led.toggle().ok();
+            }
         }
 
         wait_for_interrupt();
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::stm32f3xx_hal::interrupt;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+
+use stm32f3_discovery::button;
+use stm32f3_discovery::button::interrupt::{ButtonInterrupt, TriggerMode};
+use stm32f3_discovery::exti::ExtiManager;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::power::Power;
+use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+
+static BUTTON_INTERRUPT: Mutex<RefCell<Option<ButtonInterrupt>>> = Mutex::new(RefCell::new(None));
+
+#[interrupt]
+fn EXTI0() {
+    free(|cs| {
+        if let Some(button_int) = BUTTON_INTERRUPT.borrow(cs).borrow_mut().as_mut() {
+            //If we don't clear the interrupt to signal it's been serviced, it will continue to fire.
+            button_int.clear();
+        }
+    });
+}
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+    let mut status_led = leds.ld3;
+
+    let mut power = Power::new(device_periphs.PWR, &mut reset_and_clock_control.apb1);
+
+    let syscfg = device_periphs.SYSCFG.constrain(&mut reset_and_clock_control.apb2);
+    let mut exti = ExtiManager::new(device_periphs.EXTI, syscfg);
+    let button_int = button::interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
+    free(|cs| BUTTON_INTERRUPT.borrow(cs).replace(Some(button_int)));
+
+    loop {
+        // All clocks are stopped until the button is pressed.
+        // The leds keep their state because the GPIO registers are retained in Stop mode.
+        power.stop(&mut core_periphs.SCB);
+        status_led.toggle().ok();
+    }
+}
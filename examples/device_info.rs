@@ -0,0 +1,35 @@
+//! Prints the MCU's unique ID, flash size and revision over ITM once a second
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::device_info::DeviceInfo;
+use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let clocks = reset_and_clock_control.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(core_periphs.SYST, clocks);
+
+    // setup ITM output
+    let stim = &mut core_periphs.ITM.stim[0];
+
+    let info = DeviceInfo::read();
+    let mut serial = [0; 24];
+    loop {
+        iprintln!(stim, "{}", info);
+        iprintln!(stim, "serial number {}, board {:08x}", info.uid.to_hex(&mut serial), info.uid.short_id());
+        delay.delay_ms(1000u16);
+    }
+}
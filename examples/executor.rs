@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+use cortex_m_rt::{entry, exception};
+
+use stm32f3_discovery::stm32f3xx_hal::interrupt;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::asynch::{self, Delay};
+use stm32f3_discovery::executor::{Executor, ReadySet};
+use stm32f3_discovery::pin_task;
+use stm32f3_discovery::button::interrupt::TriggerMode;
+use stm32f3_discovery::exti::ExtiManager;
+use stm32f3_discovery::button::{self, UserButton};
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+
+#[interrupt]
+fn EXTI0() {
+    asynch::on_exti_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    asynch::on_systick();
+}
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let delay = Delay::new(core_periphs.SYST, clocks);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+    let mut status_led = leds.ld3;
+    let mut button_led = leds.ld10;
+
+    // initialize user button and its interrupt
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+    let syscfg = device_periphs.SYSCFG.constrain(&mut reset_and_clock_control.apb2);
+    let mut exti = ExtiManager::new(device_periphs.EXTI, syscfg);
+    let mut button_int = button::interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
+
+    // each task sleeps until its own interrupt arrives, and the core sleeps whenever both are waiting
+    let blink = async {
+        loop {
+            status_led.toggle().ok();
+            delay.delay_ms(250).await;
+        }
+    };
+    let toggle = async {
+        loop {
+            button.wait_for_press(&mut button_int).await;
+            button_led.toggle().ok();
+        }
+    };
+    pin_task!(blink, toggle);
+
+    static READY: ReadySet = ReadySet::new();
+    let mut executor = Executor::<2>::new(&READY);
+    executor.spawn(blink).ok();
+    executor.spawn(toggle).ok();
+    executor.run();
+
+    unreachable!("the tasks never complete")
+}
@@ -9,11 +9,8 @@ use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
 use stm32f3_discovery::stm32f3xx_hal::prelude::*;
 use stm32f3_discovery::stm32f3xx_hal::pac;
 
-use stm32f3xx_hal::gpio::gpioe;
-use stm32f3xx_hal::gpio::{Output, PushPull};
-
-use stm32f3_discovery::leds::{Direction, Leds};
-use stm32f3_discovery::switch_hal::{Switch, ActiveHigh, OutputSwitch};
+use stm32f3_discovery::leds::{Direction, Led, Leds};
+use stm32f3_discovery::switch_hal::OutputSwitch;
 
 #[entry]
 fn main() -> ! {
@@ -43,22 +40,20 @@ fn main() -> ! {
     loop {
         let fast_delay = 50u16;
         for direction in Direction::iter() {
-            let led = &mut leds.for_direction(*direction);
-    
+            let led = &mut leds[*direction];
             led.on().ok();
             delay.delay_ms(fast_delay);
             led.off().ok();
             delay.delay_ms(fast_delay);
         }
 
-        slow_blink(leds.for_direction(Direction::North), &mut delay);
-        slow_blink(leds.for_direction(Direction::South), &mut delay);
-        slow_blink(leds.for_direction(Direction::East), &mut delay);
-        slow_blink(leds.for_direction(Direction::West), &mut delay);
+        for direction in &[Direction::North, Direction::South, Direction::East, Direction::West] {
+            slow_blink(&mut leds[*direction], &mut delay);
+        }
     }
 }
 
-fn slow_blink(switch: &mut Switch<gpioe::PEx<Output<PushPull>>, ActiveHigh>, delay: &mut Delay) {
+fn slow_blink(switch: &mut Led, delay: &mut Delay) {
     let slow_delay = 250u16;
     switch.on().ok();
     delay.delay_ms(slow_delay);
@@ -69,8 +69,12 @@ fn main() -> ! {
         iter.next_back().map(|led| led.on().ok());
         delay.delay_ms(ms_delay);
         // we're in the middle, so panic if either of the next two calls returns a led
-        iter.next().map(|_| panic!("Got a Some!"));
-        iter.next_back().map(|_| panic!("Got a Some!"));
+        if iter.next().is_some() {
+            panic!("Got a Some!");
+        }
+        if iter.next_back().is_some() {
+            panic!("Got a Some!");
+        }
         
         // turn everything back off
         for led in &mut leds {
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::time::Duration;
+
+use cortex_m::iprintln;
+use cortex_m_rt::{entry, exception};
+
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::{InputSwitch, OutputSwitch, ToggleableOutputSwitch};
+use stm32f3_discovery::time::{self, Clock, Deadline, Periodic};
+use stm32f3_discovery::wait_for_interrupt;
+
+#[exception]
+fn SysTick() {
+    time::on_systick();
+}
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let clock = Clock::new(core_periphs.SYST, clocks);
+
+    // setup ITM output
+    let stim = &mut core_periphs.ITM.stim[0];
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+
+    // two leds blinking at their own rate, plus one that stays lit for a second after each press
+    let mut fast = Periodic::new(Duration::from_millis(125));
+    let mut slow = Periodic::new(Duration::from_millis(500));
+    let mut pressed_led = Deadline::new(Duration::from_secs(0));
+
+    loop {
+        if fast.poll() {
+            leds.ld3.toggle().ok();
+        }
+        if slow.poll() {
+            leds.ld10.toggle().ok();
+        }
+
+        if button.is_active().unwrap() {
+            if pressed_led.is_expired() {
+                iprintln!(stim, "Pressed at {}ms", clock.now().as_millis());
+            }
+            pressed_led.reset(Duration::from_secs(1));
+            leds.ld7.on().ok();
+        } else if pressed_led.is_expired() {
+            leds.ld7.off().ok();
+        }
+
+        // SysTick wakes us up every millisecond
+        wait_for_interrupt();
+    }
+}
@@ -0,0 +1,65 @@
+//! Reports the cause of the last reset over ITM, and flashes the leds if it was abnormal.
+//! Press the user button for a software reset.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use cortex_m::iprintln;
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::reset;
+use stm32f3_discovery::switch_hal::{InputSwitch, ToggleableOutputSwitch};
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let clocks = reset_and_clock_control.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(core_periphs.SYST, clocks);
+
+    // setup ITM output
+    let stim = &mut core_periphs.ITM.stim[0];
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+
+    let cause = reset::reset_cause();
+    iprintln!(stim, "Reset by {:?}, abnormal: {}", cause, cause.is_abnormal());
+    reset::blink_reset_cause(cause, &mut leds, &mut delay);
+
+    loop {
+        leds.ld7.toggle().ok();
+        delay.delay_ms(500u16);
+
+        if button.is_active().unwrap() {
+            SCB::sys_reset();
+        }
+    }
+}
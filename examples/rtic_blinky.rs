@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+#[rtic::app(device = stm32f3_discovery::stm32f3xx_hal::pac, dispatchers = [SPI1])]
+mod app {
+    use stm32f3_discovery::leds::{Led, Leds};
+    use stm32f3_discovery::rtic::{Duration, MonoTimer};
+    use stm32f3_discovery::clocks::ClockProfile;
+    use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+    use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+
+    #[monotonic(binds = TIM2, default = true)]
+    type Mono = MonoTimer;
+
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {
+        led: Led,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut flash = cx.device.FLASH.constrain();
+        let mut rcc = cx.device.RCC.constrain();
+        let (clocks, _) = ClockProfile::Max72MHz.freeze(rcc.cfgr, &mut flash.acr);
+        let mono = MonoTimer::new(cx.device.TIM2, clocks, &mut rcc.apb1).unwrap();
+
+        let mut gpioe = cx.device.GPIOE.split(&mut rcc.ahb);
+        let leds = Leds::new(
+            gpioe.pe8,
+            gpioe.pe9,
+            gpioe.pe10,
+            gpioe.pe11,
+            gpioe.pe12,
+            gpioe.pe13,
+            gpioe.pe14,
+            gpioe.pe15,
+            &mut gpioe.moder,
+            &mut gpioe.otyper,
+        );
+
+        blink::spawn().unwrap();
+        (Shared {}, Local { led: leds.ld3 }, init::Monotonics(mono))
+    }
+
+    #[task(local = [led])]
+    fn blink(cx: blink::Context) {
+        cx.local.led.toggle().ok();
+        blink::spawn_after(Duration::millis(500)).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+#[rtic::app(device = stm32f3_discovery::stm32f3xx_hal::pac, dispatchers = [SPI1])]
+mod app {
+    use stm32f3_discovery::leds::Leds;
+    use stm32f3_discovery::rtic::{Board, Button, MonoTimer};
+    use stm32f3_discovery::clocks::ClockProfile;
+    use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+    use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+
+    #[monotonic(binds = TIM2, default = true)]
+    type Mono = MonoTimer;
+
+    #[shared]
+    struct Shared {
+        leds: Leds,
+    }
+
+    #[local]
+    struct Local {
+        button: Button,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut flash = cx.device.FLASH.constrain();
+        let mut rcc = cx.device.RCC.constrain();
+        let (clocks, _) = ClockProfile::Max72MHz.freeze(rcc.cfgr, &mut flash.acr);
+
+        let mut gpioa = cx.device.GPIOA.split(&mut rcc.ahb);
+        let mut gpiob = cx.device.GPIOB.split(&mut rcc.ahb);
+        let mut gpioe = cx.device.GPIOE.split(&mut rcc.ahb);
+        let board = Board::new(
+            gpioa.pa0,
+            &mut gpioa.moder,
+            &mut gpioa.pupdr,
+            gpiob.pb6,
+            gpiob.pb7,
+            &mut gpiob.moder,
+            &mut gpiob.otyper,
+            &mut gpiob.afrl,
+            gpioe.pe8,
+            gpioe.pe9,
+            gpioe.pe10,
+            gpioe.pe11,
+            gpioe.pe12,
+            gpioe.pe13,
+            gpioe.pe14,
+            gpioe.pe15,
+            &mut gpioe.moder,
+            &mut gpioe.otyper,
+            cx.device.I2C1,
+            cx.device.TIM2,
+            cx.device.EXTI,
+            cx.device.SYSCFG,
+            clocks,
+            &mut rcc.apb1,
+            &mut rcc.apb2,
+        )
+        .unwrap();
+
+        (
+            Shared { leds: board.leds },
+            Local { button: board.button },
+            init::Monotonics(board.mono),
+        )
+    }
+
+    #[task(binds = EXTI0, local = [button], shared = [leds])]
+    fn button_pressed(mut cx: button_pressed::Context) {
+        // pa0 has a low pass filter on it, so no need to debounce in software
+        cx.local.button.on_interrupt();
+        toggle::spawn().ok();
+        cx.shared.leds.lock(|leds| leds.ld3.toggle().ok());
+    }
+
+    #[task(shared = [leds])]
+    fn toggle(mut cx: toggle::Context) {
+        // show that a software task can share the leds with the interrupt handler
+        cx.shared.leds.lock(|leds| leds.ld10.toggle().ok());
+    }
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+#[rtic::app(device = stm32f3_discovery::stm32f3xx_hal::pac, dispatchers = [SPI1])]
+mod app {
+    use cortex_m::iprintln;
+    use cortex_m::peripheral::ITM;
+
+    use stm32f3_discovery::accelerometer::{Accelerometer, RawAccelerometer};
+    use stm32f3_discovery::rtic::{Board, CompassDrdy, MonoTimer};
+    use stm32f3_discovery::clocks::ClockProfile;
+    use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+    #[monotonic(binds = TIM2, default = true)]
+    type Mono = MonoTimer;
+
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {
+        compass: CompassDrdy,
+        itm: ITM,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let mut flash = cx.device.FLASH.constrain();
+        let mut rcc = cx.device.RCC.constrain();
+        let (clocks, _) = ClockProfile::Max72MHz.freeze(rcc.cfgr, &mut flash.acr);
+
+        let mut gpioa = cx.device.GPIOA.split(&mut rcc.ahb);
+        let mut gpiob = cx.device.GPIOB.split(&mut rcc.ahb);
+        let mut gpioe = cx.device.GPIOE.split(&mut rcc.ahb);
+        let board = Board::new(
+            gpioa.pa0,
+            &mut gpioa.moder,
+            &mut gpioa.pupdr,
+            gpiob.pb6,
+            gpiob.pb7,
+            &mut gpiob.moder,
+            &mut gpiob.otyper,
+            &mut gpiob.afrl,
+            gpioe.pe8,
+            gpioe.pe9,
+            gpioe.pe10,
+            gpioe.pe11,
+            gpioe.pe12,
+            gpioe.pe13,
+            gpioe.pe14,
+            gpioe.pe15,
+            &mut gpioe.moder,
+            &mut gpioe.otyper,
+            cx.device.I2C1,
+            cx.device.TIM2,
+            cx.device.EXTI,
+            cx.device.SYSCFG,
+            clocks,
+            &mut rcc.apb1,
+            &mut rcc.apb2,
+        )
+        .unwrap();
+
+        (
+            Shared {},
+            Local { compass: board.compass, itm: cx.core.ITM },
+            init::Monotonics(board.mono),
+        )
+    }
+
+    // runs every time the magnetometer has a new sample, instead of polling every second
+    #[task(binds = EXTI2_TSC, local = [compass, itm])]
+    fn compass_ready(cx: compass_ready::Context) {
+        let stim = &mut cx.local.itm.stim[0];
+
+        let mag = cx.local.compass.on_interrupt().unwrap();
+        iprintln!(stim, "RawMag:{:?}", mag);
+
+        let compass = cx.local.compass.compass();
+        let accel = compass.accel_raw().unwrap();
+        iprintln!(stim, "RawAccel:{:?}", accel);
+
+        let normalized_accel = compass.accel_norm().unwrap();
+        iprintln!(stim, "G-Force:{:?}", normalized_accel);
+    }
+}
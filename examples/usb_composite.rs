@@ -0,0 +1,141 @@
+//! Streams raw accelerometer samples on the vendor bulk endpoint while the console takes commands:
+//! `start` and `stop` control the stream, and `id` prints the board's serial number.
+//! LD10 is lit while streaming.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::fmt::Write;
+use core::time::Duration;
+
+use cortex_m_rt::{entry, exception};
+
+use stm32f3_discovery::accelerometer::vector::I16x3;
+use stm32f3_discovery::accelerometer::RawAccelerometer;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::compass::Compass;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::OutputSwitch;
+use stm32f3_discovery::time::{self, Clock, Periodic};
+use stm32f3_discovery::usb;
+use stm32f3_discovery::usb::composite::{UsbComposite, MAX_SAMPLES_PER_FRAME};
+
+#[exception]
+fn SysTick() {
+    time::on_systick();
+}
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::Max72MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let clock = Clock::new(core_periphs.SYST, clocks);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize the accelerometer
+    let mut gpiob = device_periphs.GPIOB.split(&mut reset_and_clock_control.ahb);
+    let mut compass = Compass::new(
+        gpiob.pb6,
+        gpiob.pb7,
+        &mut gpiob.moder,
+        &mut gpiob.otyper,
+        &mut gpiob.afrl,
+        device_periphs.I2C1,
+        clocks,
+        &mut reset_and_clock_control.apb1,
+    )
+    .unwrap();
+
+    // initialize the USER USB connector
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let usb_bus = usb::bus(
+        device_periphs.USB,
+        gpioa.pa11,
+        gpioa.pa12,
+        &mut gpioa.moder,
+        &mut gpioa.otyper,
+        &mut gpioa.afrh,
+        clocks,
+    );
+    let mut usb = UsbComposite::new(&usb_bus);
+
+    // the accelerometer updates at 400 Hz, so don't sample any faster
+    let mut sample_timer = Periodic::new(Duration::from_millis(3));
+    let mut samples = [I16x3::default(); MAX_SAMPLES_PER_FRAME];
+    let mut count = 0;
+    let mut first_sample_at = 0;
+    let mut streaming = false;
+
+    let mut command = [0u8; 16];
+    let mut command_len = 0;
+
+    loop {
+        usb.poll();
+
+        // collect console input into a line, and run it on enter
+        let mut buffer = [0u8; 64];
+        let received = usb.read(&mut buffer).unwrap_or(0);
+        for &byte in &buffer[..received] {
+            if byte != b'\r' && byte != b'\n' {
+                if command_len < command.len() {
+                    command[command_len] = byte;
+                    command_len += 1;
+                }
+                continue;
+            }
+            match &command[..command_len] {
+                b"start" => streaming = true,
+                b"stop" => streaming = false,
+                b"id" => {
+                    writeln!(usb, "{}\r", usb::serial_number()).ok();
+                }
+                b"" => {}
+                _ => {
+                    usb.write_all(b"commands: start, stop, id\r\n").ok();
+                }
+            }
+            command_len = 0;
+        }
+
+        if streaming {
+            leds.ld10.on().ok();
+        } else {
+            leds.ld10.off().ok();
+            count = 0;
+        }
+
+        if streaming && sample_timer.poll() {
+            if count == 0 {
+                first_sample_at = clock.now().as_millis() as u32;
+            }
+            samples[count] = compass.accel_raw().unwrap();
+            count += 1;
+            if count == samples.len() {
+                usb.push_frame(first_sample_at, &samples).ok();
+                count = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Two ways into the ROM bootloader to flash new firmware over USB with `dfu-util`, without the ST-LINK:
+//! hold the user button while resetting the board, or type `dfu` on the USB serial port.
+//! LD3 blinks while the application runs.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::time::Duration;
+
+use cortex_m_rt::{entry, exception};
+
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::bootloader;
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::ToggleableOutputSwitch;
+use stm32f3_discovery::time::{self, Clock, Deadline, Periodic};
+use stm32f3_discovery::usb::{self, UsbSerial};
+
+#[exception]
+fn SysTick() {
+    time::on_systick();
+}
+
+#[entry]
+fn main() -> ! {
+    // before anything else is set up
+    bootloader::enter_system_dfu_if_button_held();
+
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::UsbCapable48MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let _clock = Clock::new(core_periphs.SYST, clocks);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize the USER USB connector
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let usb_bus = usb::bus(
+        device_periphs.USB,
+        gpioa.pa11,
+        gpioa.pa12,
+        &mut gpioa.moder,
+        &mut gpioa.otyper,
+        &mut gpioa.afrh,
+        clocks,
+    );
+    let mut serial = UsbSerial::new(&usb_bus);
+
+    let mut blink = Periodic::new(Duration::from_millis(250));
+    let mut command = [0u8; 8];
+    let mut command_len = 0;
+
+    loop {
+        serial.poll();
+        if blink.poll() {
+            leds.ld3.toggle().ok();
+        }
+
+        let mut buffer = [0u8; 64];
+        let received = serial.read(&mut buffer).unwrap_or(0);
+        for &byte in &buffer[..received] {
+            if byte != b'\r' && byte != b'\n' {
+                if command_len < command.len() {
+                    command[command_len] = byte;
+                    command_len += 1;
+                }
+                continue;
+            }
+            if &command[..command_len] == b"dfu" {
+                serial.write_all(b"entering the bootloader\r\n").ok();
+                // give the host a moment to read the reply
+                let flushed = Deadline::new(Duration::from_millis(20));
+                while !flushed.is_expired() {
+                    serial.poll();
+                }
+                bootloader::enter_system_dfu();
+            }
+            command_len = 0;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! A presentation remote: click for the next slide, double click for the previous one,
+//! hold the button to blank the screen, or tilt the board right or left to step through the slides.
+//! LD10 is lit once the host has configured the keyboard.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::time::Duration;
+
+use cortex_m_rt::{entry, exception};
+
+use stm32f3_discovery::accelerometer::Accelerometer;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::button::events::ButtonEvents;
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::compass::Compass;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::OutputSwitch;
+use stm32f3_discovery::time::{self, Clock, Periodic};
+use stm32f3_discovery::usb;
+use stm32f3_discovery::usb::keyboard::{Gesture, KeyMap, TiltGestures, UsbKeyboard};
+
+#[exception]
+fn SysTick() {
+    time::on_systick();
+}
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::UsbCapable48MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+    let clock = Clock::new(core_periphs.SYST, clocks);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize the accelerometer
+    let mut gpiob = device_periphs.GPIOB.split(&mut reset_and_clock_control.ahb);
+    let mut compass = Compass::new(
+        gpiob.pb6,
+        gpiob.pb7,
+        &mut gpiob.moder,
+        &mut gpiob.otyper,
+        &mut gpiob.afrl,
+        device_periphs.I2C1,
+        clocks,
+        &mut reset_and_clock_control.apb1,
+    )
+    .unwrap();
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+    let mut button = ButtonEvents::new(button);
+
+    // initialize the USER USB connector
+    let usb_bus = usb::bus(
+        device_periphs.USB,
+        gpioa.pa11,
+        gpioa.pa12,
+        &mut gpioa.moder,
+        &mut gpioa.otyper,
+        &mut gpioa.afrh,
+        clocks,
+    );
+    let mut keyboard = UsbKeyboard::new(&usb_bus);
+
+    let keymap = KeyMap::presenter();
+    let mut tilt = TiltGestures::default();
+    let mut tick = Periodic::new(Duration::from_millis(10));
+
+    loop {
+        keyboard.poll();
+        if !tick.poll() {
+            continue;
+        }
+
+        if keyboard.is_configured() {
+            leds.ld10.on().ok();
+        } else {
+            leds.ld10.off().ok();
+        }
+
+        let now = clock.now().as_millis() as u32;
+        let from_button = button.update(now).unwrap().and_then(Gesture::from_button);
+        let from_tilt = tilt.update(compass.accel_norm().unwrap());
+        if let Some(action) = from_button.or(from_tilt).and_then(|gesture| keymap.action(gesture)) {
+            keyboard.tap(action);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+use cortex_m_rt::entry;
+
+use core::fmt::Write;
+
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::clocks::ClockProfile;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::switch_hal::{InputSwitch, OutputSwitch};
+use stm32f3_discovery::usb::{self, UsbSerial};
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut flash = device_periphs.FLASH.constrain();
+    let (clocks, _) = ClockProfile::UsbCapable48MHz.freeze(reset_and_clock_control.cfgr, &mut flash.acr);
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+    let mut was_pressed = false;
+
+    // initialize the USER USB connector
+    let usb_bus = usb::bus(
+        device_periphs.USB,
+        gpioa.pa11,
+        gpioa.pa12,
+        &mut gpioa.moder,
+        &mut gpioa.otyper,
+        &mut gpioa.afrh,
+        clocks,
+    );
+    let mut serial = UsbSerial::new(&usb_bus);
+
+    loop {
+        if !serial.poll() {
+            continue;
+        }
+
+        // show when a terminal has the port open
+        if serial.is_connected() {
+            leds.ld10.on().ok();
+        } else {
+            leds.ld10.off().ok();
+        }
+
+        let pressed = button.is_active().unwrap();
+        if pressed && !was_pressed {
+            writeln!(serial, "Button pressed\r").ok();
+        }
+        was_pressed = pressed;
+
+        // echo back in upper case
+        let mut buffer = [0u8; 64];
+        if let Ok(count) = serial.read(&mut buffer) {
+            buffer[..count].make_ascii_uppercase();
+            serial.write_all(&buffer[..count]).ok();
+        }
+    }
+}
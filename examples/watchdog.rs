@@ -0,0 +1,72 @@
+//! Feeds the watchdog while LD7 blinks. Hold the user button to stop feeding it:
+//! the board resets after half a second, and flashes the red leds on the way back up.
+#![no_std]
+#![no_main]
+
+extern crate panic_itm;
+
+use core::time::Duration;
+
+use cortex_m::iprintln;
+use cortex_m_rt::entry;
+
+use stm32f3_discovery::stm32f3xx_hal::delay::Delay;
+use stm32f3_discovery::stm32f3xx_hal::pac;
+use stm32f3_discovery::stm32f3xx_hal::prelude::*;
+
+use stm32f3_discovery::button::UserButton;
+use stm32f3_discovery::leds::Leds;
+use stm32f3_discovery::reset;
+use stm32f3_discovery::switch_hal::{InputSwitch, ToggleableOutputSwitch};
+use stm32f3_discovery::watchdog::{Watchdog, WatchdogConfig};
+
+#[entry]
+fn main() -> ! {
+    let device_periphs = pac::Peripherals::take().unwrap();
+    let mut reset_and_clock_control = device_periphs.RCC.constrain();
+
+    let mut core_periphs = cortex_m::Peripherals::take().unwrap();
+    let mut flash = device_periphs.FLASH.constrain();
+    let clocks = reset_and_clock_control.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(core_periphs.SYST, clocks);
+
+    // setup ITM output
+    let stim = &mut core_periphs.ITM.stim[0];
+
+    // initialize user leds
+    let mut gpioe = device_periphs.GPIOE.split(&mut reset_and_clock_control.ahb);
+    let mut leds = Leds::new(
+        gpioe.pe8,
+        gpioe.pe9,
+        gpioe.pe10,
+        gpioe.pe11,
+        gpioe.pe12,
+        gpioe.pe13,
+        gpioe.pe14,
+        gpioe.pe15,
+        &mut gpioe.moder,
+        &mut gpioe.otyper,
+    );
+
+    // initialize user button
+    let mut gpioa = device_periphs.GPIOA.split(&mut reset_and_clock_control.ahb);
+    let button = UserButton::new(gpioa.pa0, &mut gpioa.moder, &mut gpioa.pupdr);
+
+    if Watchdog::caused_last_reset() {
+        iprintln!(stim, "Reset by the watchdog");
+    }
+    reset::blink_reset_cause(reset::reset_cause(), &mut leds, &mut delay);
+
+    let config = WatchdogConfig { timeout: Duration::from_millis(500), ..WatchdogConfig::default() };
+    let mut watchdog = Watchdog::start(device_periphs.IWDG, &device_periphs.DBGMCU, config);
+    iprintln!(stim, "Watchdog timeout: {} ms", watchdog.timeout().as_millis());
+
+    loop {
+        leds.ld7.toggle().ok();
+        delay.delay_ms(100u16);
+
+        if !button.is_active().unwrap() {
+            watchdog.feed();
+        }
+    }
+}
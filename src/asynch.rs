@@ -0,0 +1,556 @@
+//! Async/await support for the board's interrupt driven peripherals
+//!
+//! Board futures register a [`Waker`] and return `Pending`,
+//! and the matching interrupt handler wakes them up again by calling a hook from this module:
+//!
+//! - [`on_exti_interrupt()`] from every `EXTIx` handler whose lines are awaited,
+//!   e.g. with [`crate::exti::ExtiLine::wait()`], [`crate::button::UserButton::wait_for_press()`]
+//!   or [`crate::compass::Compass::accel_ready()`].
+//! - [`on_systick()`] from the `SysTick` exception, which drives [`Delay`].
+//!
+//! The futures don't depend on a particular executor.
+//! [`block_on()`] is a minimal one that sleeps until an interrupt arrives whenever the future isn't ready,
+//! and [`select()`] runs two futures concurrently on it.
+//!
+//! # Example
+//!
+//! ```
+//! #[interrupt]
+//! fn EXTI0() {
+//!     asynch::on_exti_interrupt();
+//! }
+//!
+//! #[exception]
+//! fn SysTick() {
+//!     asynch::on_systick();
+//! }
+//!
+//! let delay = Delay::new(core_periphs.SYST, clocks);
+//! block_on(async {
+//!     loop {
+//!         // blink until the button is pressed
+//!         let blink = async {
+//!             loop {
+//!                 leds.ld3.toggle().ok();
+//!                 delay.delay_ms(100).await;
+//!             }
+//!         };
+//!         select(blink, button.wait_for_press(&mut button_int)).await;
+//!         leds.ld3.off().ok();
+//!         button.wait_for_press(&mut button_int).await;
+//!     }
+//! });
+//! ```
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use core::time::Duration;
+use cortex_m::interrupt::{self, CriticalSection, Mutex};
+use cortex_m::peripheral::SYST;
+use stm32f3xx_hal::pac::EXTI;
+use stm32f3xx_hal::rcc::Clocks;
+
+use crate::time::{self, Clock, Deadline, Instant};
+
+/// Number of tasks that can wait on the same interrupt source at once.
+/// Any further waiters are polled continuously instead of sleeping.
+pub const MAX_WAITERS: usize = 4;
+
+/// Tasks waiting on any EXTI line
+static EXTI_WAITERS: ExtiWaiters = ExtiWaiters::new();
+
+/// Tasks waiting on a [`Delay`]
+static TICK_WAKERS: WakerSet<MAX_WAITERS> = WakerSet::new();
+
+/// Wakers registered by tasks waiting on the same interrupt source,
+/// for use in a `static` shared with an interrupt handler.
+///
+/// Registering the same task twice only keeps one copy.
+/// If all `N` slots are taken, the new task is woken right away so it polls again rather than being lost.
+pub struct WakerSet<const N: usize> {
+    wakers: Mutex<RefCell<[Option<Waker>; N]>>,
+}
+
+impl<const N: usize> WakerSet<N> {
+    // needed to build the array in a const fn, `Waker` isn't `Copy`
+    const EMPTY: Option<Waker> = None;
+
+    /// Creates an empty set. Being `const`, it can be used to initialize a `static`.
+    pub const fn new() -> Self {
+        WakerSet { wakers: Mutex::new(RefCell::new([Self::EMPTY; N])) }
+    }
+
+    /// Adds `waker` to the set, to be woken on the next [`Self::wake_all()`]
+    pub fn register(&self, waker: &Waker) {
+        let registered = critical_section(|cs| {
+            let mut wakers = self.wakers.borrow(cs).borrow_mut();
+            if wakers.iter().flatten().any(|w| w.will_wake(waker)) {
+                return true;
+            }
+            match wakers.iter_mut().find(|w| w.is_none()) {
+                Some(slot) => {
+                    *slot = Some(waker.clone());
+                    true
+                }
+                None => false,
+            }
+        });
+        if !registered {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Wakes and removes every registered task
+    pub fn wake_all(&self) {
+        let wakers = critical_section(|cs| self.wakers.borrow(cs).replace([Self::EMPTY; N]));
+        // wake outside of the critical section, a waker may do arbitrary work
+        for waker in wakers.iter().flatten() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<const N: usize> Default for WakerSet<N> {
+    fn default() -> Self {
+        WakerSet::new()
+    }
+}
+
+/// Runs `f` with interrupts disabled.
+/// Host tests have no interrupts to disable (and `interrupt::free` panics there),
+/// so they just run `f`, and each test uses its own `WakerSet`s so threads don't share them.
+fn critical_section<T, F: FnOnce(&CriticalSection) -> T>(f: F) -> T {
+    #[cfg(not(test))]
+    return interrupt::free(f);
+    // Safety: See above, nothing else can be inside the critical section
+    #[cfg(test)]
+    f(unsafe { &CriticalSection::new() })
+}
+
+/// The EXTI registers used to wait on lines, so the masking can be checked against a fake
+pub(crate) trait ExtiRegisters {
+    /// Bits of the pending register PR1
+    fn pending(&self) -> u32;
+    /// Clears the pending bits in `mask`
+    fn clear_pending(&self, mask: u32);
+    /// Calls `f` with PR1 and the interrupt mask register IMR1 without racing other handlers,
+    /// and writes the IMR1 it returns
+    fn update_mask<T, F: FnOnce(u32, u32) -> (u32, T)>(&self, f: F) -> T;
+}
+
+/// The real EXTI peripheral
+struct Exti;
+
+impl ExtiRegisters for Exti {
+    fn pending(&self) -> u32 {
+        // Safety: Reading PR1 has no side effects
+        unsafe { (*EXTI::ptr()).pr1.read().bits() }
+    }
+
+    fn clear_pending(&self, mask: u32) {
+        // Safety: PR1 is write 1 to clear, so this single write only affects the lines in `mask`
+        unsafe { (*EXTI::ptr()).pr1.write(|w| w.bits(mask)) }
+    }
+
+    fn update_mask<T, F: FnOnce(u32, u32) -> (u32, T)>(&self, f: F) -> T {
+        crate::exti::modify_exti(|exti| {
+            let (imr, result) = f(exti.pr1.read().bits(), exti.imr1.read().bits());
+            exti.imr1.write(|w| unsafe { w.bits(imr) });
+            result
+        })
+    }
+}
+
+/// Tasks waiting on EXTI lines, and the lines they are waiting on
+pub(crate) struct ExtiWaiters {
+    wakers: WakerSet<MAX_WAITERS>,
+    /// Lines with a pending [`crate::exti::ExtiLine::wait()`]
+    waiting: AtomicU32,
+    /// Lines that a finished wait left unmasked, because they were unmasked when it started.
+    /// If one fires before the next wait, it is masked again so it doesn't keep firing unserviced,
+    /// and the next wait still treats it as unmasked.
+    restored: AtomicU32,
+}
+
+impl ExtiWaiters {
+    const fn new() -> Self {
+        ExtiWaiters { wakers: WakerSet::new(), waiting: AtomicU32::new(0), restored: AtomicU32::new(0) }
+    }
+
+    /// Masks the fired lines that are awaited, or were restored by a wait, and wakes the waiting tasks
+    fn service<R: ExtiRegisters>(&self, exti: &R) {
+        let waiting = self.waiting.load(Ordering::Acquire);
+        let watched = waiting | self.restored.load(Ordering::Acquire);
+        let fired = exti.update_mask(|pending, imr| {
+            let fired = pending & imr & watched;
+            // masking the line stops it firing again until the task has run
+            (imr & !fired, fired)
+        });
+        if fired & waiting != 0 {
+            self.wakers.wake_all();
+        }
+    }
+
+    /// Waits for an edge on the lines in `mask`, and puts their mask back the way it was afterwards
+    async fn edge<R: ExtiRegisters>(&self, exti: &R, mask: u32) {
+        struct Waiting<'a, R: ExtiRegisters> {
+            waiters: &'a ExtiWaiters,
+            exti: &'a R,
+            mask: u32,
+            unmasked: u32,
+        }
+        impl<R: ExtiRegisters> Drop for Waiting<'_, R> {
+            // also runs when a `select` drops the future before the edge arrives
+            fn drop(&mut self) {
+                let (mask, unmasked) = (self.mask, self.unmasked);
+                self.exti.update_mask(|_, imr| (imr & !mask | unmasked, ()));
+                self.waiters.restored.fetch_and(!mask, Ordering::AcqRel);
+                self.waiters.restored.fetch_or(unmasked, Ordering::AcqRel);
+                self.waiters.waiting.fetch_and(!mask, Ordering::AcqRel);
+            }
+        }
+
+        let restored = self.restored.load(Ordering::Acquire);
+        let unmasked = exti.update_mask(|_, imr| (imr, (imr | restored) & mask));
+        self.waiting.fetch_or(mask, Ordering::AcqRel);
+        let _waiting = Waiting { waiters: self, exti, mask, unmasked };
+        poll_fn(|cx| {
+            if exti.pending() & mask != 0 {
+                exti.clear_pending(mask);
+                return Poll::Ready(());
+            }
+            self.wakers.register(cx.waker());
+            // an edge from here on raises the interrupt, which wakes us up
+            exti.update_mask(|_, imr| (imr | mask, ()));
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Stops restoring the lines in `mask`, because the application took over their mask
+    fn forget(&self, mask: u32) {
+        self.restored.fetch_and(!mask, Ordering::AcqRel);
+    }
+}
+
+/// Services the awaited EXTI lines. Call it from the `EXTIx` handlers for those lines.
+///
+/// The awaited lines that fired are masked and their tasks woken;
+/// the future clears the pending bit once it is polled again.
+/// A wait leaves its line masked or unmasked the way it found it,
+/// and until the next wait, a line it left unmasked is masked again if it fires,
+/// so an edge in between doesn't keep firing while nobody clears it.
+/// Other lines are left alone, so a handler can service them as usual,
+/// as can a handler that services a previously awaited line before calling this.
+pub fn on_exti_interrupt() {
+    EXTI_WAITERS.service(&Exti)
+}
+
+/// Waits for an edge on the EXTI lines in `mask`, see [`crate::exti::ExtiLine::wait()`]
+pub(crate) async fn exti_edge(mask: u32) {
+    EXTI_WAITERS.edge(&Exti, mask).await
+}
+
+/// Called when the application masks or unmasks the lines in `mask` itself,
+/// so a finished wait no longer masks them for it
+pub(crate) fn exti_mask_changed(mask: u32) {
+    EXTI_WAITERS.forget(mask)
+}
+
+/// Counts a millisecond and wakes the tasks waiting on a [`Delay`]. Call it from the `SysTick` exception.
+/// This also advances [`crate::time`], so there's no need to call [`time::on_systick()`] as well.
+pub fn on_systick() {
+    time::on_systick();
+    TICK_WAKERS.wake_all();
+}
+
+/// Non-blocking delays, driven by the SysTick [`Clock`].
+///
+/// Unlike the HAL's blocking `Delay`, any number of tasks can wait at the same time,
+/// so the delays take `&self`.
+pub struct Delay {
+    clock: Clock,
+}
+
+impl Delay {
+    /// Starts the SysTick [`Clock`].
+    /// The `SysTick` exception must call [`on_systick()`].
+    pub fn new(syst: SYST, clocks: Clocks) -> Self {
+        Delay::from_clock(Clock::new(syst, clocks))
+    }
+
+    /// Uses a [`Clock`] that has already been started.
+    /// The `SysTick` exception must call [`on_systick()`] rather than [`time::on_systick()`].
+    pub fn from_clock(clock: Clock) -> Self {
+        Delay { clock }
+    }
+
+    /// The current time
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Completes after at least `ms` milliseconds
+    pub async fn delay_ms(&self, ms: u32) {
+        self.delay(Duration::from_millis(u64::from(ms))).await
+    }
+
+    /// Completes after at least `duration`, rounded up to the next millisecond
+    pub async fn delay(&self, duration: Duration) {
+        let whole_millis = Duration::from_millis(duration.as_millis() as u64);
+        let rounded_up = if whole_millis == duration {
+            duration
+        } else {
+            whole_millis + Duration::from_millis(1)
+        };
+        // the current tick is already partly over, so wait for one more
+        self.until(self.now() + rounded_up + Duration::from_millis(1)).await
+    }
+
+    /// Completes once `instant` has passed
+    pub async fn until(&self, instant: Instant) {
+        let deadline = Deadline::at(instant);
+        poll_fn(|cx| {
+            if deadline.is_expired() {
+                Poll::Ready(())
+            } else {
+                TICK_WAKERS.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Releases the clock
+    pub fn free(self) -> Clock {
+        self.clock
+    }
+}
+
+/// Set by [`block_on()`]'s waker
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |_| RawWaker::new(core::ptr::null(), &VTABLE),
+    |_| WOKEN.store(true, Ordering::Release),
+    |_| WOKEN.store(true, Ordering::Release),
+    |_| {},
+);
+
+/// Runs `future` to completion on the current stack,
+/// sleeping with `WFI` whenever it is waiting on an interrupt.
+///
+/// This is the minimal way to run the board's futures. Run several of them concurrently with [`select()`],
+/// or use any other executor instead.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = future;
+    // Safety: `future` is shadowed, so it can't be moved again after being pinned
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    // Safety: The vtable functions ignore the data pointer
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        WOKEN.store(false, Ordering::Release);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // With interrupts disabled, an interrupt that arrives after the check still ends the WFI,
+        // and is serviced once the critical section ends.
+        interrupt::free(|_| {
+            if !WOKEN.load(Ordering::Acquire) {
+                crate::wait_for_interrupt();
+            }
+        });
+    }
+}
+
+/// Result of [`select()`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Either<A, B> {
+    First(A),
+    Second(B),
+}
+
+/// Future returned by [`select()`]
+pub struct Select<A, B> {
+    first: A,
+    second: B,
+}
+
+/// Runs both futures until either completes, and drops the other one.
+/// If both are ready at the same time, `first` wins.
+pub fn select<A: Future, B: Future>(first: A, second: B) -> Select<A, B> {
+    Select { first, second }
+}
+
+impl<A: Future, B: Future> Future for Select<A, B> {
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: The fields are never moved out of the pinned `Select`
+        let this = unsafe { self.get_unchecked_mut() };
+        let first = unsafe { Pin::new_unchecked(&mut this.first) };
+        if let Poll::Ready(output) = first.poll(cx) {
+            return Poll::Ready(Either::First(output));
+        }
+        let second = unsafe { Pin::new_unchecked(&mut this.second) };
+        second.poll(cx).map(Either::Second)
+    }
+}
+
+/// Future that calls `f` until it returns `Ready`
+struct PollFn<F>(F);
+
+impl<T, F: FnMut(&mut Context<'_>) -> Poll<T>> Future for PollFn<F> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        // Safety: The closure is never moved out of the pinned `PollFn`
+        let f = unsafe { &mut self.get_unchecked_mut().0 };
+        f(cx)
+    }
+}
+
+pub(crate) fn poll_fn<T, F: FnMut(&mut Context<'_>) -> Poll<T>>(f: F) -> impl Future<Output = T> {
+    PollFn(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    const LINE: u32 = 1 << 4;
+    const OTHER_LINE: u32 = 1 << 1;
+
+    #[derive(Default)]
+    struct FakeExti {
+        pr: Cell<u32>,
+        imr: Cell<u32>,
+    }
+
+    impl FakeExti {
+        fn unmasked(imr: u32) -> Self {
+            FakeExti { pr: Cell::new(0), imr: Cell::new(imr) }
+        }
+
+        /// An edge arrives, which raises the interrupt if the line is unmasked
+        fn edge(&self, waiters: &ExtiWaiters, mask: u32) {
+            self.pr.set(self.pr.get() | mask);
+            if self.imr.get() & mask != 0 {
+                waiters.service(self);
+            }
+        }
+    }
+
+    impl ExtiRegisters for FakeExti {
+        fn pending(&self) -> u32 {
+            self.pr.get()
+        }
+
+        fn clear_pending(&self, mask: u32) {
+            self.pr.set(self.pr.get() & !mask);
+        }
+
+        fn update_mask<T, F: FnOnce(u32, u32) -> (u32, T)>(&self, f: F) -> T {
+            let (imr, result) = f(self.pr.get(), self.imr.get());
+            self.imr.set(imr);
+            result
+        }
+    }
+
+    static NOOP_VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(core::ptr::null(), &NOOP_VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+
+    fn poll<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        // Safety: The vtable functions ignore the data pointer
+        let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &NOOP_VTABLE)) };
+        future.poll(&mut Context::from_waker(&waker))
+    }
+
+    /// Waits for one edge on `LINE`, checking the line is unmasked while waiting
+    fn wait_for_edge(exti: &FakeExti, waiters: &ExtiWaiters) {
+        let edge = waiters.edge(exti, LINE);
+        crate::pin_task!(edge);
+        assert_eq!(poll(edge.as_mut()), Poll::Pending);
+        assert_eq!(exti.imr.get() & LINE, LINE);
+
+        exti.edge(waiters, LINE);
+        assert_eq!(exti.imr.get() & LINE, 0, "masked until the task runs");
+        assert_eq!(poll(edge.as_mut()), Poll::Ready(()));
+        assert_eq!(exti.pr.get(), 0);
+    }
+
+    #[test]
+    fn wait_restores_an_unmasked_line() {
+        let (exti, waiters) = (FakeExti::unmasked(LINE | OTHER_LINE), ExtiWaiters::new());
+        wait_for_edge(&exti, &waiters);
+        assert_eq!(exti.imr.get(), LINE | OTHER_LINE);
+    }
+
+    #[test]
+    fn wait_restores_a_masked_line() {
+        let (exti, waiters) = (FakeExti::unmasked(OTHER_LINE), ExtiWaiters::new());
+        wait_for_edge(&exti, &waiters);
+        assert_eq!(exti.imr.get(), OTHER_LINE);
+    }
+
+    #[test]
+    fn cancelled_wait_restores_the_line() {
+        let (exti, waiters) = (FakeExti::unmasked(0), ExtiWaiters::new());
+        {
+            let edge = waiters.edge(&exti, LINE);
+            crate::pin_task!(edge);
+            assert_eq!(poll(edge), Poll::Pending);
+            assert_eq!(exti.imr.get(), LINE);
+        }
+        assert_eq!(exti.imr.get(), 0);
+        assert_eq!(waiters.waiting.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn edge_between_waits_is_kept_for_the_next_wait() {
+        let (exti, waiters) = (FakeExti::unmasked(LINE), ExtiWaiters::new());
+        wait_for_edge(&exti, &waiters);
+
+        // nobody waits, so the line is masked rather than left to fire forever
+        exti.edge(&waiters, LINE);
+        assert_eq!(exti.imr.get(), 0);
+
+        let edge = waiters.edge(&exti, LINE);
+        crate::pin_task!(edge);
+        assert_eq!(poll(edge), Poll::Ready(()));
+        assert_eq!(exti.imr.get(), LINE, "still counts as unmasked");
+    }
+
+    #[test]
+    fn application_takes_the_mask_back() {
+        let (exti, waiters) = (FakeExti::unmasked(LINE), ExtiWaiters::new());
+        wait_for_edge(&exti, &waiters);
+        waiters.forget(LINE);
+
+        // e.g. a handler servicing the line itself, which then calls the hook
+        exti.edge(&waiters, LINE);
+        assert_eq!(exti.imr.get(), LINE);
+    }
+
+    #[test]
+    fn other_lines_are_left_alone() {
+        let (exti, waiters) = (FakeExti::unmasked(OTHER_LINE), ExtiWaiters::new());
+        let edge = waiters.edge(&exti, LINE);
+        crate::pin_task!(edge);
+        assert_eq!(poll(edge.as_mut()), Poll::Pending);
+
+        exti.edge(&waiters, OTHER_LINE);
+        assert_eq!(exti.imr.get(), LINE | OTHER_LINE);
+        assert_eq!(exti.pr.get(), OTHER_LINE);
+        assert_eq!(poll(edge), Poll::Pending);
+    }
+}
@@ -0,0 +1,168 @@
+//! Jumping to the STM32F303's built-in bootloader in system memory
+//!
+//! The ROM bootloader can flash new firmware over USB DFU on the USER USB connector,
+//! e.g. with `dfu-util -a 0 -s 0x08000000:leave -D firmware.bin`, or over USART1 and USART2.
+//! It normally runs when BOOT0 is held high at reset; [`enter_system_dfu()`] starts it from firmware instead.
+//!
+//! USB DFU needs the 8 MHz clock on the HSE input, which the ST-LINK's MCO provides,
+//! see [`crate::clocks`].
+//!
+//! # Example
+//!
+//! Holding the user button while the board resets starts the bootloader instead of the application.
+//! Check for it first thing in `main`, before setting up any peripherals:
+//!
+//! ```
+//! #[entry]
+//! fn main() -> ! {
+//!     bootloader::enter_system_dfu_if_button_held();
+//!
+//!     let device_periphs = pac::Peripherals::take().unwrap();
+//!     // ...
+//! }
+//! ```
+use cortex_m::peripheral::{NVIC, SCB, SYST};
+use stm32f3xx_hal::pac;
+
+/// Start of system memory, where the bootloader's vector table is
+const SYSTEM_MEMORY: usize = 0x1fff_d800;
+
+/// Cycles D+ is held low for, 10 ms at the 8 MHz HSI
+const USB_DISCONNECT_CYCLES: u32 = 80_000;
+
+/// Cycles to let the button's low pass filter settle after the pin is set up, ~125 µs at the 8 MHz reset clock
+const BUTTON_SETTLE_CYCLES: u32 = 1_000;
+
+/// Puts the MCU back in its reset state as far as the bootloader is concerned,
+/// then jumps to the bootloader in system memory. It doesn't return to the application.
+///
+/// On the way there it:
+///
+/// - disables and clears all interrupts, stops SysTick, and clears the pending SysTick and PendSV exceptions
+///   and the fault status, so the bootloader starts as if from a reset,
+/// - resets all peripherals and switches back to the 8 MHz HSI with the PLL and HSE off,
+/// - pulls D+ low for a moment, so the host notices the application is gone and enumerates the bootloader,
+/// - maps system memory at address 0 and moves the vector table there,
+/// - and loads the bootloader's stack pointer before jumping to its reset vector.
+///
+/// Pending writes, e.g. on the USB device, are dropped.
+pub fn enter_system_dfu() -> ! {
+    cortex_m::interrupt::disable();
+
+    // Safety: Interrupts are disabled and the application doesn't run again, so nothing else uses these registers
+    unsafe {
+        let syst = &(*SYST::PTR);
+        syst.csr.write(0);
+        syst.rvr.write(0);
+        // writing the current value also clears COUNTFLAG
+        syst.cvr.write(0);
+
+        let nvic = &(*NVIC::PTR);
+        for (icer, icpr) in nvic.icer.iter().zip(nvic.icpr.iter()) {
+            icer.write(u32::MAX);
+            icpr.write(u32::MAX);
+        }
+
+        // SysTick or PendSV could have become pending before interrupts were disabled,
+        // and would run the bootloader's handlers as soon as it enables interrupts
+        const PENDSTCLR: u32 = 1 << 25;
+        const PENDSVCLR: u32 = 1 << 27;
+        let scb = &(*SCB::PTR);
+        scb.icsr.write(PENDSTCLR | PENDSVCLR);
+        // the fault status bits are write 1 to clear
+        scb.cfsr.write(scb.cfsr.read());
+        scb.hfsr.write(scb.hfsr.read());
+
+        reset_clocks_and_peripherals();
+        force_usb_reconnect();
+
+        let rcc = &(*pac::RCC::ptr());
+        rcc.apb2enr.modify(|_, w| w.syscfgen().enabled());
+        let syscfg = &(*pac::SYSCFG::ptr());
+        syscfg.cfgr1.modify(|_, w| w.mem_mode().system_flash());
+        scb.vtor.write(0);
+
+        // everything that could interrupt is off, and the bootloader expects interrupts to be enabled
+        cortex_m::interrupt::enable();
+        cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32)
+    }
+}
+
+/// Starts the bootloader with [`enter_system_dfu()`] if the user button is held down, otherwise returns.
+///
+/// The button is read directly from the registers, so call this before taking the peripherals.
+/// GPIOA is left as it was after reset.
+pub fn enter_system_dfu_if_button_held() {
+    if button_held() {
+        enter_system_dfu();
+    }
+}
+
+/// True if the user button on PA0 is held down, read without taking any peripherals
+fn button_held() -> bool {
+    // Safety: GPIOA is only clocked for the read, PA0 is an input after reset
+    unsafe {
+        let rcc = &(*pac::RCC::ptr());
+        let was_enabled = rcc.ahbenr.read().iopaen().is_enabled();
+        rcc.ahbenr.modify(|_, w| w.iopaen().enabled());
+        cortex_m::asm::delay(BUTTON_SETTLE_CYCLES);
+
+        let held = (*pac::GPIOA::ptr()).idr.read().idr0().bit_is_set();
+
+        if !was_enabled {
+            rcc.ahbenr.modify(|_, w| w.iopaen().disabled());
+        }
+        held
+    }
+}
+
+/// Pulls D+ (PA12) low against the board's fixed pull-up, like [`crate::usb::bus()`] does,
+/// then puts GPIOA back in its reset state
+///
+/// # Safety
+///
+/// Must only run once the clocks and peripherals are reset.
+unsafe fn force_usb_reconnect() {
+    let rcc = &(*pac::RCC::ptr());
+    let gpioa = &(*pac::GPIOA::ptr());
+    rcc.ahbenr.modify(|_, w| w.iopaen().enabled());
+    gpioa.bsrr.write(|w| w.br12().reset());
+    gpioa.moder.modify(|_, w| w.moder12().output());
+    cortex_m::asm::delay(USB_DISCONNECT_CYCLES);
+
+    rcc.ahbrstr.write(|w| w.ioparst().reset());
+    rcc.ahbrstr.reset();
+    rcc.ahbenr.reset();
+}
+
+/// Resets every peripheral on the AHB and APB buses and puts the clock tree back to its reset configuration
+///
+/// # Safety
+///
+/// Any peripheral handles in the application are invalid afterwards.
+unsafe fn reset_clocks_and_peripherals() {
+    let rcc = &(*pac::RCC::ptr());
+
+    // back to the HSI before the PLL and HSE can be turned off
+    rcc.cr.modify(|_, w| w.hsion().on());
+    while rcc.cr.read().hsirdy().is_not_ready() {}
+    rcc.cfgr.modify(|_, w| w.sw().hsi());
+    while !rcc.cfgr.read().sws().is_hsi() {}
+    rcc.cfgr.reset();
+    rcc.cfgr2.reset();
+    rcc.cfgr3.reset();
+    rcc.cr.modify(|_, w| w.pllon().off().csson().off().hseon().off());
+    rcc.cr.modify(|_, w| w.hsebyp().not_bypassed());
+    rcc.cir.reset();
+
+    rcc.ahbrstr.write(|w| w.bits(u32::MAX));
+    rcc.ahbrstr.reset();
+    rcc.apb1rstr.write(|w| w.bits(u32::MAX));
+    rcc.apb1rstr.reset();
+    rcc.apb2rstr.write(|w| w.bits(u32::MAX));
+    rcc.apb2rstr.reset();
+
+    rcc.ahbenr.reset();
+    rcc.apb1enr.reset();
+    rcc.apb2enr.reset();
+}
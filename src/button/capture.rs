@@ -0,0 +1,179 @@
+//! Hardware timestamping of the `UserButton` with TIM2 input capture
+//!
+//! PA0 doubles as TIM2_CH1, so TIM2 can latch the time of every press and release
+//! with microsecond resolution, without the CPU polling the pin.
+//! Channel 1 captures rising edges (press) and channel 2 captures falling edges (release), both from PA0.
+//! TIM2 is a 32 bit timer, so timestamps wrap around every ~71 minutes;
+//! durations are calculated with wrapping arithmetic and stay correct across the wrap.
+//!
+//! # Example
+//!
+//! ```
+//! let mut capture = ButtonCapture::new(
+//!     device_periphs.TIM2,
+//!     button,
+//!     &mut gpioa.moder,
+//!     &mut gpioa.otyper,
+//!     &mut gpioa.afrl,
+//!     clocks,
+//!     &mut rcc.apb1,
+//! )
+//! .map_err(|(error, _tim2, _button)| error)
+//! .unwrap();
+//!
+//! loop {
+//!     if let Some(CaptureEvent::Released { duration_us: Some(duration_us), .. }) = capture.read() {
+//!         iprintln!(stim, "Held for: {} us", duration_us);
+//!     }
+//! }
+//! ```
+use super::UserButton;
+use cortex_m::peripheral::NVIC;
+use stm32f3xx_hal::gpio::gpioa::{self, PA0};
+use stm32f3xx_hal::gpio::{PushPull, AF1};
+use stm32f3xx_hal::pac::{Interrupt, TIM2};
+use stm32f3xx_hal::rcc::{Clocks, APB1};
+
+use crate::timer;
+
+/// Timer ticks per second, so one tick is one microsecond
+const TICK_HZ: u32 = 1_000_000;
+
+/// Button edges captured by [`ButtonCapture`], with timestamps in microseconds
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CaptureEvent {
+    /// The button went down at `at_us`
+    Pressed { at_us: u32 },
+    /// The button went up at `at_us`, after being held for `duration_us`.
+    /// `duration_us` is `None` if the matching press was not captured,
+    /// e.g. the button was already down when capturing started.
+    Released { at_us: u32, duration_us: Option<u32> },
+}
+
+/// TIM2 input capture on the user button, see the [module documentation](self)
+pub struct ButtonCapture {
+    tim: TIM2,
+    pin: PA0<AF1<PushPull>>,
+    last_press: Option<u32>,
+    last_duration: Option<u32>,
+}
+
+impl ButtonCapture {
+    /// Takes over the user button and TIM2, and starts capturing.
+    ///
+    /// Fails if the TIM2 clock can't be divided down to exactly 1 MHz, e.g. because it is slower than that.
+    /// Nothing is configured in that case, and TIM2 and the button are handed back along with the error.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        tim2: TIM2,
+        button: UserButton,
+        moder: &mut gpioa::MODER,
+        otyper: &mut gpioa::OTYPER,
+        afrl: &mut gpioa::AFRL,
+        clocks: Clocks,
+        apb1: &mut APB1,
+    ) -> Result<Self, (timer::Error, TIM2, UserButton)> {
+        if let Err(error) = timer::start_free_running(&tim2, clocks, apb1, TICK_HZ) {
+            return Err((error, tim2, button));
+        }
+        let pin = button.free().into_af1_push_pull(moder, otyper, afrl);
+
+        // Channel 1 watches TI1 (PA0) for rising edges, channel 2 watches TI1 for falling edges.
+        // The board already filters the button, so a short digital filter is plenty.
+        const FCK_INT_N8: u8 = 0b0011;
+        tim2.ccmr1_input().write(|w| {
+            w.cc1s().ti1();
+            w.ic1f().fck_int_n8();
+            w.cc2s().ti1();
+            // the PAC has no named variants for IC2F
+            w.ic2f().bits(FCK_INT_N8)
+        });
+        tim2.ccer.write(|w| {
+            w.cc1p().clear_bit();
+            w.cc1np().clear_bit();
+            w.cc1e().set_bit();
+            w.cc2p().set_bit();
+            w.cc2np().clear_bit();
+            w.cc2e().set_bit()
+        });
+
+        Ok(ButtonCapture { tim: tim2, pin, last_press: None, last_duration: None })
+    }
+
+    /// Current timer value in microseconds, on the same clock as the captured timestamps
+    pub fn now_us(&self) -> u32 {
+        self.tim.cnt.read().bits()
+    }
+
+    /// Returns the next captured edge, if any.
+    ///
+    /// The hardware holds one press and one release at a time.
+    /// If a press and release are both waiting, the press is returned first.
+    pub fn read(&mut self) -> Option<CaptureEvent> {
+        let status = self.tim.sr.read();
+        if status.cc1if().bit_is_set() {
+            // reading CCR1 clears the flag
+            let at_us = self.tim.ccr1.read().bits();
+            self.last_press = Some(at_us);
+            Some(CaptureEvent::Pressed { at_us })
+        } else if status.cc2if().bit_is_set() {
+            // reading CCR2 clears the flag
+            let at_us = self.tim.ccr2.read().bits();
+            let duration_us = self.last_press.take().map(|press| at_us.wrapping_sub(press));
+            if duration_us.is_some() {
+                self.last_duration = duration_us;
+            }
+            Some(CaptureEvent::Released { at_us, duration_us })
+        } else {
+            None
+        }
+    }
+
+    /// True if the hardware captured an edge while the previous one was still unread,
+    /// meaning at least one timestamp was lost. Clears the indication.
+    pub fn overcaptured(&mut self) -> bool {
+        let status = self.tim.sr.read();
+        let overcaptured = status.cc1of().bit_is_set() || status.cc2of().bit_is_set();
+        if overcaptured {
+            // only the overcapture flags, so a capture that just arrived isn't lost
+            const CC1OF: u32 = 1 << 9;
+            const CC2OF: u32 = 1 << 10;
+            timer::clear_flags(&self.tim, CC1OF | CC2OF);
+        }
+        overcaptured
+    }
+
+    /// Timestamp of the most recent press that hasn't been released yet
+    pub fn pressed_since_us(&self) -> Option<u32> {
+        self.last_press
+    }
+
+    /// Duration of the most recently completed press
+    pub fn last_duration_us(&self) -> Option<u32> {
+        self.last_duration
+    }
+
+    /// Raises the TIM2 interrupt on every captured edge,
+    /// so [`Self::read()`] can be called from the `TIM2` handler instead of the main loop.
+    pub fn listen(&mut self) {
+        self.tim.dier.modify(|_, w| w.cc1ie().set_bit().cc2ie().set_bit());
+        unsafe {
+            NVIC::unmask(Interrupt::TIM2);
+        }
+    }
+
+    /// Stops raising the TIM2 interrupt. Edges are still captured.
+    pub fn unlisten(&mut self) {
+        NVIC::mask(Interrupt::TIM2);
+        self.tim.dier.modify(|_, w| w.cc1ie().clear_bit().cc2ie().clear_bit());
+    }
+
+    /// Stops the timer and releases TIM2 along with a plain `UserButton` on PA0
+    pub fn free(self, moder: &mut gpioa::MODER, pupdr: &mut gpioa::PUPDR) -> (TIM2, UserButton) {
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        self.tim.dier.reset();
+        self.tim.ccer.reset();
+        let pa0 = self.pin.into_floating_input(moder, pupdr);
+        (self.tim, UserButton::new(pa0, moder, pupdr))
+    }
+}
@@ -0,0 +1,343 @@
+//! Debounced press, release, click, double click, and long press detection for the `UserButton`
+//!
+//! [`ButtonEvents`] is a pure state machine driven by millisecond timestamps,
+//! so it works equally well with a periodic tick or a free running clock
+//! and can be exercised on the host with any [`InputSwitch`].
+use super::UserButton;
+use switch_hal::InputSwitch;
+
+/// Events reported by [`ButtonEvents`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ButtonEvent {
+    /// The button went down (after debouncing)
+    Pressed,
+    /// The button went up (after debouncing)
+    Released,
+    /// A short press that was not followed by a second press within the double click window
+    Click,
+    /// Two short presses within the double click window
+    DoubleClick,
+    /// The button has been held down for the long press threshold.
+    /// Reported once per press.
+    /// If the press started within the double click window of a click, that `Click` is reported first.
+    LongPress,
+    /// The button is still held after a long press, reported every `held_interval_ms`
+    /// with the number of milliseconds it has been down.
+    Held(u32),
+}
+
+/// Timing thresholds for [`ButtonEvents`], all in milliseconds
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Thresholds {
+    /// How long the button level has to be stable before a change is accepted
+    pub debounce_ms: u32,
+    /// How long the button has to be held before `LongPress` is reported
+    pub long_press_ms: u32,
+    /// Maximum time between releasing the first click and pressing the second one
+    /// for the two to count as a `DoubleClick`.
+    /// Setting it to 0 disables double click detection, and `Click` is reported on release.
+    pub double_click_ms: u32,
+    /// How often `Held` is reported after a long press
+    pub held_interval_ms: u32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            debounce_ms: 20,
+            long_press_ms: 1000,
+            double_click_ms: 300,
+            held_interval_ms: 250,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Idle,
+    Down {
+        since: u32,
+        second_click: bool,
+        last_report: Option<u32>,
+    },
+    AwaitingSecondClick {
+        released_at: u32,
+    },
+}
+
+/// Turns the raw level of a button into [`ButtonEvent`]s.
+///
+/// Call [`Self::update()`] regularly (every 5-10 ms works well) with the current time in milliseconds.
+/// At most one event is returned per update; if a transition produces two events
+/// (e.g. `Released` followed by `DoubleClick`), the second is returned by the next update.
+///
+/// # Example
+///
+/// ```
+/// let mut events = ButtonEvents::new(button);
+/// let mut now = 0u32;
+/// loop {
+///     delay.delay_ms(10u16);
+///     now += 10;
+///     match events.update(now) {
+///         Ok(Some(ButtonEvent::Click)) => leds.ld3.toggle().ok(),
+///         Ok(Some(ButtonEvent::LongPress)) => leds.ld10.toggle().ok(),
+///         _ => None,
+///     };
+/// }
+/// ```
+pub struct ButtonEvents<B = UserButton> {
+    button: B,
+    thresholds: Thresholds,
+    state: State,
+    /// Debounced level
+    pressed: bool,
+    /// Last raw level and when it was first seen
+    raw_pressed: bool,
+    raw_since: u32,
+    pending: Option<ButtonEvent>,
+}
+
+impl<B: InputSwitch> ButtonEvents<B> {
+    /// Wraps `button` using the default [`Thresholds`]
+    pub fn new(button: B) -> Self {
+        ButtonEvents::with_thresholds(button, Thresholds::default())
+    }
+
+    /// Wraps `button` using custom [`Thresholds`]
+    pub fn with_thresholds(button: B, thresholds: Thresholds) -> Self {
+        ButtonEvents {
+            button,
+            thresholds,
+            state: State::Idle,
+            pressed: false,
+            raw_pressed: false,
+            raw_since: 0,
+            pending: None,
+        }
+    }
+
+    /// Reads the button and advances the state machine to `now_ms`.
+    /// Read errors are passed through without changing any state.
+    pub fn update(&mut self, now_ms: u32) -> Result<Option<ButtonEvent>, B::Error> {
+        let level = self.button.is_active()?;
+        Ok(self.update_level(now_ms, level))
+    }
+
+    /// Consumes the state machine and returns the wrapped button
+    pub fn free(self) -> B {
+        self.button
+    }
+}
+
+impl<B> ButtonEvents<B> {
+    /// Current thresholds
+    pub fn thresholds(&self) -> Thresholds {
+        self.thresholds
+    }
+
+    /// Replaces the thresholds, taking effect from the next update
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Debounced state of the button
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Advances the state machine to `now_ms` with a raw button level that was read elsewhere,
+    /// e.g. from an interrupt handler or a test.
+    ///
+    /// Timestamps may wrap around `u32::MAX`, as long as updates are less than ~49 days apart.
+    pub fn update_level(&mut self, now_ms: u32, pressed: bool) -> Option<ButtonEvent> {
+        if pressed != self.raw_pressed {
+            self.raw_pressed = pressed;
+            self.raw_since = now_ms;
+        }
+
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+
+        let stable = now_ms.wrapping_sub(self.raw_since) >= self.thresholds.debounce_ms;
+        if stable && self.raw_pressed != self.pressed {
+            self.pressed = self.raw_pressed;
+            return if self.pressed {
+                Some(self.on_press(now_ms))
+            } else {
+                Some(self.on_release(now_ms))
+            };
+        }
+
+        self.on_tick(now_ms)
+    }
+
+    fn on_press(&mut self, now_ms: u32) -> ButtonEvent {
+        let second_click = matches!(self.state, State::AwaitingSecondClick { .. });
+        self.state = State::Down { since: now_ms, second_click, last_report: None };
+        ButtonEvent::Pressed
+    }
+
+    fn on_release(&mut self, now_ms: u32) -> ButtonEvent {
+        self.state = match self.state {
+            // the press was already reported as a long press, so it doesn't count as a click
+            State::Down { last_report: Some(_), .. } => State::Idle,
+            State::Down { second_click: true, .. } => {
+                self.pending = Some(ButtonEvent::DoubleClick);
+                State::Idle
+            }
+            State::Down { .. } if self.thresholds.double_click_ms == 0 => {
+                self.pending = Some(ButtonEvent::Click);
+                State::Idle
+            }
+            State::Down { .. } => State::AwaitingSecondClick { released_at: now_ms },
+            other => other,
+        };
+        ButtonEvent::Released
+    }
+
+    fn on_tick(&mut self, now_ms: u32) -> Option<ButtonEvent> {
+        match self.state {
+            State::Down { since, second_click, last_report } => {
+                let held = now_ms.wrapping_sub(since);
+                match last_report {
+                    None if held >= self.thresholds.long_press_ms => {
+                        self.state = State::Down { since, second_click: false, last_report: Some(now_ms) };
+                        if second_click {
+                            // the first press was a click of its own, report it before it is lost
+                            self.pending = Some(ButtonEvent::LongPress);
+                            Some(ButtonEvent::Click)
+                        } else {
+                            Some(ButtonEvent::LongPress)
+                        }
+                    }
+                    Some(last) if now_ms.wrapping_sub(last) >= self.thresholds.held_interval_ms => {
+                        self.state = State::Down { since, second_click, last_report: Some(now_ms) };
+                        Some(ButtonEvent::Held(held))
+                    }
+                    _ => None,
+                }
+            }
+            State::AwaitingSecondClick { released_at }
+                if now_ms.wrapping_sub(released_at) >= self.thresholds.double_click_ms =>
+            {
+                self.state = State::Idle;
+                Some(ButtonEvent::Click)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// A button that is never read, the tests feed the levels through `update_level()`
+    struct NoButton;
+
+    impl InputSwitch for NoButton {
+        type Error = core::convert::Infallible;
+        fn is_active(&self) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+    }
+
+    const TICK_MS: u32 = 5;
+
+    struct Harness {
+        events: ButtonEvents<NoButton>,
+        now: u32,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Harness { events: ButtonEvents::new(NoButton), now: 0 }
+        }
+
+        /// Holds the button at `pressed` for `duration_ms`, updating every tick
+        fn hold(&mut self, pressed: bool, duration_ms: u32) -> Vec<ButtonEvent> {
+            let mut events = Vec::new();
+            for _ in 0..duration_ms / TICK_MS {
+                self.now = self.now.wrapping_add(TICK_MS);
+                events.extend(self.events.update_level(self.now, pressed));
+            }
+            events
+        }
+    }
+
+    use ButtonEvent::*;
+
+    #[test]
+    fn single_click() {
+        let mut button = Harness::new();
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 250), [Released]);
+        // reported once the double click window has passed
+        assert_eq!(button.hold(false, 100), [Click]);
+        assert_eq!(button.hold(false, 1000), []);
+    }
+
+    #[test]
+    fn bounces_are_filtered() {
+        let mut button = Harness::new();
+        assert_eq!(button.hold(true, 10), []);
+        assert_eq!(button.hold(false, 10), []);
+        assert_eq!(button.hold(true, 10), []);
+        assert_eq!(button.hold(false, 500), []);
+        assert!(!button.events.is_pressed());
+    }
+
+    #[test]
+    fn double_click() {
+        let mut button = Harness::new();
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 150), [Released]);
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 100), [Released, DoubleClick]);
+        assert_eq!(button.hold(false, 1000), []);
+    }
+
+    #[test]
+    fn click_without_double_click_window() {
+        let mut button = Harness::new();
+        button.events.set_thresholds(Thresholds { double_click_ms: 0, ..Thresholds::default() });
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 30), [Released, Click]);
+    }
+
+    #[test]
+    fn long_press_and_held() {
+        let mut button = Harness::new();
+        // debounced after 20 ms, so long after 1020 ms and held every 250 ms from there
+        assert_eq!(button.hold(true, 1015), [Pressed]);
+        assert_eq!(button.hold(true, 10), [LongPress]);
+        assert_eq!(button.hold(true, 250), [Held(1250)]);
+        assert_eq!(button.hold(true, 250), [Held(1500)]);
+        // a long press isn't also a click
+        assert_eq!(button.hold(false, 1000), [Released]);
+    }
+
+    #[test]
+    fn click_then_long_press() {
+        let mut button = Harness::new();
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 150), [Released]);
+        assert_eq!(button.hold(true, 1015), [Pressed]);
+        // the pending long press comes with the next update
+        assert_eq!(button.hold(true, 15), [Click, LongPress]);
+        assert_eq!(button.hold(false, 1000), [Released]);
+    }
+
+    #[test]
+    fn timestamps_wrap() {
+        let mut button = Harness::new();
+        button.now = u32::MAX - 50;
+        assert_eq!(button.hold(true, 100), [Pressed]);
+        assert_eq!(button.hold(false, 400), [Released, Click]);
+    }
+}
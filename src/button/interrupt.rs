@@ -1,8 +1,7 @@
 //! Provides interrupt features for `UserButton` on PA0 for the board
+use crate::exti::{self, ExtiLine, ExtiManager, Source};
 use cortex_m::peripheral::NVIC;
-use stm32f3xx_hal::pac::exti::{FTSR1, IMR1, RTSR1};
-use stm32f3xx_hal::pac::syscfg::EXTICR1;
-use stm32f3xx_hal::pac::{Interrupt, EXTI, SYSCFG};
+use stm32f3xx_hal::pac::EXTI;
 
 /// Used to clear the external interrupt pending register for the user button without moving the EXTI peripheral into global static state.
 ///
@@ -18,65 +17,127 @@ use stm32f3xx_hal::pac::{Interrupt, EXTI, SYSCFG};
 ///     button::interrupt::clear();
 /// }
 /// ```
+#[deprecated(since = "0.8.0", note = "use the `ButtonInterrupt` handle returned by `enable` instead")]
 pub fn clear() {
-    unsafe {
-        let exti = &(*stm32f3xx_hal::pac::EXTI::ptr());
-        exti.pr1.write(|w| w.pr0().set_bit())
-    }
+    clear_pending()
 }
 
-pub enum TriggerMode {
-    Rising,
-    Falling,
-    Both,
-}
+pub use crate::exti::TriggerMode;
 
-/// Configures and enables interrupt for the `UserButton` on PA0.
+/// Claims EXTI0 for the `UserButton` on PA0 from an [`ExtiManager`], then configures and enables the interrupt.
+///
+/// Returns a [`ButtonInterrupt`] handle for servicing and reconfiguring the interrupt at runtime.
+/// The manager owns EXTI0 from then on, so there is only ever one handle:
+/// calling this again, or claiming EXTI0 for the gyro's INT1 on PE0, returns [`exti::Error::LineInUse`]
+/// until the line is handed back with [`ExtiManager::release()`].
 ///
 /// # Example
 ///
 /// ```
 /// let device_periphs = pac::Peripherals::take().unwrap();
-/// button::interrupt::enable(&device_periphs.EXTI, &device_periphs.SYSCFG, TriggerMode::Rising);
+/// let mut rcc = device_periphs.RCC.constrain();
+/// let syscfg = device_periphs.SYSCFG.constrain(&mut rcc.apb2);
+/// let mut exti = ExtiManager::new(device_periphs.EXTI, syscfg);
+/// let button_int = button::interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
 /// ```
-pub fn enable(external_interrupts: &EXTI, sysconfig: &SYSCFG, mode: TriggerMode) {
+pub fn enable(manager: &mut ExtiManager, mode: TriggerMode) -> Result<ButtonInterrupt, exti::Error> {
     // See chapter 14 of the reference manual
     // https://www.st.com/content/ccc/resource/technical/document/reference_manual/4a/19/6e/18/9d/92/43/32/DM00043574.pdf/files/DM00043574.pdf/jcr:content/translations/en.DM00043574.pdf
+    manager.claim(Source::USER_BUTTON, mode).map(ButtonInterrupt)
+}
 
-    configure_exti0(&external_interrupts.imr1);
-    map_exti0_to_pa0(&sysconfig.exticr1);
+/// Handle to the EXTI0 interrupt for the `UserButton`, returned by [`enable`].
+///
+/// The handle is the only way to service and reconfigure the interrupt,
+/// so move it to wherever the `EXTI0` handler can reach it (e.g. a `Mutex` or an RTIC local resource).
+///
+/// # Example
+///
+/// ```
+/// static BUTTON_INT: Mutex<RefCell<Option<ButtonInterrupt>>> = Mutex::new(RefCell::new(None));
+///
+/// #[interrupt]
+/// fn EXTI0() {
+///     free(|cs| {
+///         if let Some(button_int) = BUTTON_INT.borrow(cs).borrow_mut().as_mut() {
+///             // If we don't clear the interrupt to signal it's been serviced, it will continue to fire.
+///             button_int.clear();
+///         }
+///     });
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ButtonInterrupt(ExtiLine);
 
-    match mode {
-        TriggerMode::Rising => configure_rising_edge_trigger(&external_interrupts.rtsr1),
-        TriggerMode::Falling => configure_falling_edge_trigger(&external_interrupts.ftsr1),
-        TriggerMode::Both => {
-            configure_rising_edge_trigger(&external_interrupts.rtsr1);
-            configure_falling_edge_trigger(&external_interrupts.ftsr1);
-        }
+impl ButtonInterrupt {
+    /// Unmasks EXTI0 in both the EXTI and the NVIC
+    pub fn enable(&mut self) {
+        self.0.enable()
     }
 
-    enable_exti0();
-}
+    /// Masks EXTI0 in both the EXTI and the NVIC.
+    /// The trigger configuration is kept, so it can be re-enabled with [`Self::enable()`].
+    pub fn disable(&mut self) {
+        self.0.disable()
+    }
 
-fn configure_exti0(interrupt_mask: &IMR1) {
-    interrupt_mask.modify(|_, w| w.mr0().set_bit())
-}
+    /// Also raises an event on every button press, which wakes the core from `WFE`
+    pub fn enable_event(&mut self) {
+        self.0.enable_event()
+    }
 
-fn map_exti0_to_pa0(external_interrupt_config: &EXTICR1) {
-    const PORT_A_CONFIG: u8 = 0x000;
-    external_interrupt_config.modify(|_, w| unsafe { w.exti0().bits(PORT_A_CONFIG) });
-}
+    /// Stops raising events for the button
+    pub fn disable_event(&mut self) {
+        self.0.disable_event()
+    }
 
-fn configure_rising_edge_trigger(rising_trigger_select: &RTSR1) {
-    rising_trigger_select.modify(|_, w| w.tr0().set_bit())
-}
+    /// True if an edge on the button is waiting to be serviced
+    pub fn is_pending(&self) -> bool {
+        self.0.is_pending()
+    }
+
+    /// Clears the pending bit for the button.
+    /// Must be called from the `EXTI0` handler, or it will continue to fire.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// Completes on the next edge of the button, or right away if one is already pending.
+    /// See [`ExtiLine::wait()`].
+    pub async fn wait(&mut self) {
+        self.0.wait().await
+    }
+
+    /// Changes which edges of the button trigger the interrupt
+    pub fn set_trigger(&mut self, mode: TriggerMode) {
+        self.0.set_trigger(mode)
+    }
+
+    /// Current NVIC priority of EXTI0
+    pub fn priority(&self) -> u8 {
+        self.0.priority()
+    }
 
-fn configure_falling_edge_trigger(falling_trigger_select: &FTSR1) {
-    falling_trigger_select.modify(|_, w| w.tr0().set_bit())
+    /// Sets the NVIC priority of EXTI0.
+    /// Lower values are higher priority, and the STM32F3 only implements the top 4 bits.
+    ///
+    /// # Safety
+    ///
+    /// Changing priority levels can break priority-based critical sections (see [`NVIC::set_priority()`]).
+    pub unsafe fn set_priority(&mut self, nvic: &mut NVIC, priority: u8) {
+        self.0.set_priority(nvic, priority)
+    }
+
+    /// Returns the underlying EXTI line, e.g. to hand it back with [`ExtiManager::release()`]
+    pub fn into_line(self) -> ExtiLine {
+        self.0
+    }
 }
 
-fn enable_exti0() {
+fn clear_pending() {
     unsafe {
-        NVIC::unmask(Interrupt::EXTI0);
+        // Safety: PR1 is write 1 to clear, so this single write only affects line 0
+        let exti = &(*EXTI::ptr());
+        exti.pr1.write(|w| w.pr0().set_bit())
     }
 }
@@ -1,6 +1,9 @@
 //! Provides access to the user button on PA0
+pub mod capture;
+pub mod events;
 pub mod interrupt;
 
+use interrupt::{ButtonInterrupt, TriggerMode};
 use stm32f3xx_hal::gpio::gpioa::PA0;
 use stm32f3xx_hal::gpio::{Input, gpioa};
 use switch_hal::{ActiveHigh, InputSwitch, IntoSwitch, Switch};
@@ -18,6 +21,40 @@ impl UserButton {
         // no need to use the internal one.
         UserButton(pa0.into_floating_input(moder, pupdr).into_active_high_switch())
     }
+
+    /// Consumes the `UserButton` and releases the underlying `PA0` pin,
+    /// e.g. to use it as the TIM2_CH1 or WKUP1 alternate function.
+    pub fn free(self) -> PA0<Input> {
+        self.0.into_pin()
+    }
+
+    /// Completes the next time the button is pressed.
+    /// Switches `interrupt` to trigger on presses, and its `EXTI0` handler must call [`crate::asynch::on_exti_interrupt()`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// block_on(async {
+    ///     loop {
+    ///         button.wait_for_press(&mut button_int).await;
+    ///         leds.ld3.toggle().ok();
+    ///     }
+    /// });
+    /// ```
+    pub async fn wait_for_press(&self, interrupt: &mut ButtonInterrupt) {
+        interrupt.set_trigger(TriggerMode::Rising);
+        // drop a press that was waiting from before we switched edges
+        interrupt.clear();
+        interrupt.wait().await
+    }
+
+    /// Completes the next time the button is released.
+    /// Switches `interrupt` to trigger on releases, see [`Self::wait_for_press()`].
+    pub async fn wait_for_release(&self, interrupt: &mut ButtonInterrupt) {
+        interrupt.set_trigger(TriggerMode::Falling);
+        interrupt.clear();
+        interrupt.wait().await
+    }
 }
 
 impl InputSwitch for UserButton {
@@ -0,0 +1,127 @@
+//! Clock configuration presets for the board
+//!
+//! The ST-LINK supplies an 8 MHz clock from its MCO output to the F303's HSE input,
+//! which has to be used in bypass mode since there is no crystal.
+//! It is more accurate than the internal 8 MHz HSI, and USB can only run from it.
+//!
+//! The MCO is missing if the ST-LINK has been cut off the board or its solder bridge is open,
+//! and the HAL would then wait forever for the HSE to start.
+//! [`ClockProfile::freeze()`] checks for the MCO first and falls back to the HSI,
+//! reporting which one it used as a [`ClockSource`].
+//!
+//! # Example
+//!
+//! ```
+//! let mut rcc = device_periphs.RCC.constrain();
+//! let mut flash = device_periphs.FLASH.constrain();
+//! let (clocks, source) = ClockProfile::Max72MHz.freeze(rcc.cfgr, &mut flash.acr);
+//! if source == ClockSource::Hsi {
+//!     iprintln!(stim, "No ST-LINK clock, running at {} Hz", clocks.sysclk().0);
+//! }
+//! ```
+use stm32f3xx_hal::flash::ACR;
+use stm32f3xx_hal::pac;
+use stm32f3xx_hal::prelude::*;
+use stm32f3xx_hal::rcc::{Clocks, APB1, CFGR};
+
+/// Frequency of the ST-LINK's MCO, in MHz
+const MCO_MHZ: u32 = 8;
+
+/// Polls of HSERDY before giving up on the MCO, or on the HSE stopping again.
+/// At the 8 MHz HSI this is well over the 5 ms the reference manual allows for HSE start up.
+const HSE_STARTUP_POLLS: u32 = 100_000;
+
+/// Board level clock configurations
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockProfile {
+    /// 72 MHz system clock with the 36 MHz maximum on APB1.
+    /// USB is usable, with the 72 MHz PLL divided by 1.5.
+    /// Falls back to 64 MHz, the most the HSI can reach.
+    Max72MHz,
+    /// 48 MHz system clock, which feeds USB directly, with APB1 at 24 MHz
+    UsbCapable48MHz,
+    /// 8 MHz straight from the HSI with the PLL and HSE off, for the lowest consumption
+    LowPower,
+}
+
+/// Oscillator that a [`ClockProfile`] ended up running from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockSource {
+    /// The ST-LINK's 8 MHz MCO in HSE bypass mode
+    HseBypass,
+    /// The internal 8 MHz RC oscillator
+    Hsi,
+}
+
+impl ClockProfile {
+    /// Applies the profile to the HAL's clock configuration for `source`, without freezing it,
+    /// e.g. to change one of the bus clocks before calling `freeze`.
+    pub fn configure(self, cfgr: CFGR, source: ClockSource) -> CFGR {
+        match (self, source) {
+            (ClockProfile::LowPower, _) => cfgr,
+            (ClockProfile::Max72MHz, ClockSource::HseBypass) => {
+                cfgr.use_hse(MCO_MHZ.MHz()).bypass_hse().sysclk(72.MHz()).pclk1(36.MHz())
+            }
+            (ClockProfile::Max72MHz, ClockSource::Hsi) => cfgr.sysclk(64.MHz()).pclk1(32.MHz()),
+            (ClockProfile::UsbCapable48MHz, ClockSource::HseBypass) => {
+                cfgr.use_hse(MCO_MHZ.MHz()).bypass_hse().sysclk(48.MHz()).pclk1(24.MHz())
+            }
+            (ClockProfile::UsbCapable48MHz, ClockSource::Hsi) => cfgr.sysclk(48.MHz()).pclk1(24.MHz()),
+        }
+    }
+
+    /// The oscillator this profile will run from, checking for the MCO if it needs the HSE
+    pub fn source(self) -> ClockSource {
+        match self {
+            ClockProfile::LowPower => ClockSource::Hsi,
+            _ if hse_bypass_available() => ClockSource::HseBypass,
+            _ => ClockSource::Hsi,
+        }
+    }
+
+    /// Configures and freezes the clocks, falling back to the HSI if the MCO is missing.
+    /// Use [`Clocks::usbclk_valid()`] to check that USB can be used.
+    ///
+    /// Must be called once at start up, while running from the reset clock configuration.
+    pub fn freeze(self, cfgr: CFGR, acr: &mut ACR) -> (Clocks, ClockSource) {
+        let source = self.source();
+        (self.configure(cfgr, source).freeze(acr), source)
+    }
+}
+
+/// True if a clock is present on the HSE input.
+///
+/// Starts the HSE in bypass mode and waits a bounded time for it to become ready, then turns it off again.
+/// If the HSE is already on, e.g. because the system clock or the PLL runs from it,
+/// it is left alone and its ready flag is returned instead.
+pub fn hse_bypass_available() -> bool {
+    // Safety: The HSE bits are only touched while nothing is using the HSE
+    let rcc = unsafe { &(*pac::RCC::ptr()) };
+    let cfgr = rcc.cfgr.read();
+    // HSEON can't be cleared while the HSE drives SYSCLK or feeds the PLL
+    let in_use = cfgr.sws().is_hse() || (cfgr.sws().is_pll() && cfgr.pllsrc().is_hse_div_prediv());
+    if in_use || rcc.cr.read().hseon().is_on() {
+        return rcc.cr.read().hserdy().is_ready();
+    }
+
+    // bypass can only be changed while the HSE is off
+    rcc.cr.modify(|_, w| w.hsebyp().bypassed());
+    rcc.cr.modify(|_, w| w.hseon().on());
+
+    let ready = (0..HSE_STARTUP_POLLS).any(|_| rcc.cr.read().hserdy().is_ready());
+
+    rcc.cr.modify(|_, w| w.hseon().off());
+    let stopped = (0..HSE_STARTUP_POLLS).any(|_| rcc.cr.read().hserdy().is_not_ready());
+    if stopped {
+        rcc.cr.modify(|_, w| w.hsebyp().not_bypassed());
+    }
+    ready
+}
+
+/// Gives access to the RCC's APB1 enable and reset registers, which the HAL doesn't expose outside of its crate.
+/// Taking APB1 mutably proves nobody else is touching them.
+pub(crate) fn modify_apb1<F: FnOnce(&pac::rcc::RegisterBlock)>(_apb1: &mut APB1, f: F) {
+    // Safety: The caller holds the HAL's APB1 handle, which stands in for these registers
+    let rcc = unsafe { &(*pac::RCC::ptr()) };
+    f(rcc)
+}
@@ -7,16 +7,26 @@ use stm32f3xx_hal::pac;
 use stm32f3xx_hal::prelude::*;
 use stm32f3xx_hal::rcc;
 
+use crate::exti::{ExtiLine, Source};
+
+/// 7 bit I2C address of the LSM303DLHC accelerometer
+const ACCEL_ADDRESS: u8 = 0b001_1001;
+/// Accelerometer register that routes interrupts to INT1
+const CTRL_REG3_A: u8 = 0x22;
+/// CTRL_REG3_A bit that raises INT1 when new accelerometer data is ready
+const I1_DRDY1: u8 = 1 << 4;
 
 type Lsm303 =
     lsm303dlhc::Lsm303dlhc<i2c::I2c<pac::I2C1, (gpiob::PB6<gpio::AF4<OpenDrain>>, gpiob::PB7<gpio::AF4<OpenDrain>>)>>;
 
 pub struct Compass {
     lsm303dlhc: Lsm303,
+    accel_drdy_enabled: bool,
 }
 
 impl Compass {
     /// Initialize the onboard Lsm303dhlc e-Compass
+    #[allow(clippy::too_many_arguments)]
     pub fn new<Pb6Mode, Pb7Mode>(
         pb6: gpiob::PB6<Pb6Mode>,
         pb7: gpiob::PB7<Pb7Mode>,
@@ -43,6 +53,7 @@ impl Compass {
         let lsm303dhlc = Lsm303::new(i2c)?;
         Ok(Compass {
             lsm303dlhc: lsm303dhlc,
+            accel_drdy_enabled: false,
         })
     }
 
@@ -52,12 +63,106 @@ impl Compass {
         Ok(I16x3::new(reading.x, reading.y, reading.z))
     }
 
+    /// Completes when a new accelerometer sample is ready to read.
+    ///
+    /// The first call routes the accelerometer's data ready signal to INT1 (PE4),
+    /// so `int1` must be the line claimed for [`Source::COMPASS_INT1`] with [`crate::exti::TriggerMode::Rising`],
+    /// and its `EXTI4` handler must call [`crate::asynch::on_exti_interrupt()`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut int1 = exti.claim(Source::COMPASS_INT1, TriggerMode::Rising).unwrap();
+    /// block_on(async {
+    ///     loop {
+    ///         compass.accel_ready(&mut int1).await.unwrap();
+    ///         let reading = compass.accel_raw().unwrap();
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `int1` isn't the line for [`Source::COMPASS_INT1`].
+    pub async fn accel_ready(&mut self, int1: &mut ExtiLine) -> Result<(), i2c::Error> {
+        assert_eq!(int1.source(), Source::COMPASS_INT1, "accel_ready needs the COMPASS_INT1 line");
+        if !self.accel_drdy_enabled {
+            self.write_accel_register(CTRL_REG3_A, I1_DRDY1)?;
+            self.accel_drdy_enabled = true;
+        }
+
+        // INT1 stays high until the sample is read, so a sample that arrived earlier has no edge left to catch
+        int1.clear();
+        if accel_int1_is_high() {
+            return Ok(());
+        }
+        int1.wait().await;
+        Ok(())
+    }
+
+    /// The driver doesn't expose register writes, so this talks to I2C1 directly,
+    /// the same way the HAL's blocking write does.
+    fn write_accel_register(&mut self, register: u8, value: u8) -> Result<(), i2c::Error> {
+        // Safety: `&mut self` owns the driver, and with it the bus, so no other transfer is in flight
+        let i2c1 = unsafe { &(*pac::I2C1::ptr()) };
+        if i2c1.isr.read().busy().is_busy() {
+            return Err(i2c::Error::Busy);
+        }
+
+        i2c1.cr2.modify(|_, w| {
+            w.add10().bit7();
+            w.sadd().bits(u16::from(ACCEL_ADDRESS) << 1);
+            w.rd_wrn().write();
+            w.nbytes().bits(2);
+            w.reload().completed();
+            w.autoend().automatic();
+            w.start().start()
+        });
+        for byte in [register, value].iter() {
+            wait_for_i2c(i2c1, |isr| isr.txis().is_empty())?;
+            i2c1.txdr.write(|w| w.txdata().bits(*byte));
+        }
+        wait_for_i2c(i2c1, |isr| isr.stopf().is_stop())?;
+        i2c1.icr.write(|w| w.stopcf().clear());
+        Ok(())
+    }
+
     /// Consume the Compass and return the underlying Lsm303dhlc
     pub fn into_lsm303dlhc(self) -> Lsm303 {
         self.lsm303dlhc
     }
 }
 
+/// Spins until `done` returns true for the I2C status, or fails on a bus error
+fn wait_for_i2c<F>(i2c1: &pac::i2c1::RegisterBlock, done: F) -> Result<(), i2c::Error>
+where
+    F: Fn(&pac::i2c1::isr::R) -> bool,
+{
+    loop {
+        let isr = i2c1.isr.read();
+        if isr.arlo().is_lost() {
+            i2c1.icr.write(|w| w.arlocf().clear());
+            return Err(i2c::Error::Arbitration);
+        } else if isr.berr().is_error() {
+            i2c1.icr.write(|w| w.berrcf().clear());
+            return Err(i2c::Error::Bus);
+        } else if isr.nackf().is_nack() {
+            while i2c1.isr.read().stopf().is_no_stop() {}
+            i2c1.icr.write(|w| w.nackcf().clear().stopcf().clear());
+            return Err(i2c::Error::Nack);
+        } else if done(&isr) {
+            return Ok(());
+        }
+    }
+}
+
+/// Level of the accelerometer INT1 pin (PE4)
+fn accel_int1_is_high() -> bool {
+    // Safety: Reading IDR has no side effects
+    let gpioe = unsafe { &(*pac::GPIOE::ptr()) };
+    gpioe.idr.read().idr4().bit_is_set()
+}
+
 impl RawAccelerometer<I16x3> for Compass {
     type Error = i2c::Error;
 
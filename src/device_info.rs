@@ -0,0 +1,150 @@
+//! Identity of the board's STM32F303: its 96 bit unique ID, flash size, and device and revision IDs
+//!
+//! The unique ID is set at the factory and differs on every chip,
+//! so it can tell boards apart, e.g. as a USB serial number or to tag logs.
+//! [`Uid::short_id()`] hashes it down to 32 bits for where the full ID is too long.
+//!
+//! # Example
+//!
+//! ```
+//! let info = DeviceInfo::read();
+//! iprintln!(stim, "{}", info);
+//! iprintln!(stim, "board {:08x}", info.uid.short_id());
+//! ```
+use core::fmt;
+use stm32f3xx_hal::pac;
+
+/// Address of the unique ID, see the reference manual's "Device electronic signature" section
+const UID_ADDRESS: usize = 0x1fff_f7ac;
+/// Address of the flash size, in KiB
+const FLASH_SIZE_ADDRESS: usize = 0x1fff_f7cc;
+
+/// The MCU's 96 bit unique ID
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Uid {
+    bytes: [u8; 12],
+}
+
+impl Uid {
+    /// Reads the unique ID
+    pub fn read() -> Self {
+        let mut bytes = [0; 12];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            // Safety: The unique ID is a read only area of system memory
+            *byte = unsafe { core::ptr::read_volatile((UID_ADDRESS + i) as *const u8) };
+        }
+        Uid { bytes }
+    }
+
+    /// Builds an ID from its bytes, in address order
+    pub const fn from_bytes(bytes: [u8; 12]) -> Self {
+        Uid { bytes }
+    }
+
+    /// The ID's bytes, in address order
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        &self.bytes
+    }
+
+    /// X coordinate of the die on the wafer
+    pub fn wafer_x(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    /// Y coordinate of the die on the wafer
+    pub fn wafer_y(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    /// Number of the wafer in its lot
+    pub fn wafer(&self) -> u8 {
+        self.bytes[4]
+    }
+
+    /// Lot number, usually ASCII
+    pub fn lot(&self) -> &[u8] {
+        &self.bytes[5..]
+    }
+
+    /// The ID as 24 upper case hex digits, two per byte in address order, e.g. for a USB serial number
+    pub fn to_hex<'b>(&self, buffer: &'b mut [u8; 24]) -> &'b str {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        for (byte, digits) in self.bytes.iter().zip(buffer.chunks_exact_mut(2)) {
+            digits[0] = HEX[usize::from(byte >> 4)];
+            digits[1] = HEX[usize::from(byte & 0xf)];
+        }
+        // Safety: The buffer only holds ASCII hex digits
+        unsafe { core::str::from_utf8_unchecked(buffer) }
+    }
+
+    /// A 32 bit hash of the ID, the 32 bit FNV-1a of its bytes in address order.
+    ///
+    /// It stays the same across firmware versions and is short enough to print on every log line,
+    /// but unlike the full ID two boards could share it, if rarely.
+    pub fn short_id(&self) -> u32 {
+        const FNV_OFFSET: u32 = 0x811c_9dc5;
+        const FNV_PRIME: u32 = 0x0100_0193;
+        self.bytes.iter().fold(FNV_OFFSET, |hash, &byte| (hash ^ u32::from(byte)).wrapping_mul(FNV_PRIME))
+    }
+}
+
+impl fmt::Display for Uid {
+    /// Same as [`Uid::to_hex()`]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_hex(&mut [0; 24]))
+    }
+}
+
+/// Size of the flash memory, in KiB
+pub fn flash_size_kb() -> u16 {
+    // Safety: The flash size is a read only area of system memory
+    unsafe { core::ptr::read_volatile(FLASH_SIZE_ADDRESS as *const u16) }
+}
+
+/// Device ID from DBGMCU_IDCODE, 0x422 for the STM32F303xB/C
+pub fn device_id() -> u16 {
+    // Safety: Reading IDCODE has no side effects
+    let dbgmcu = unsafe { &(*pac::DBGMCU::ptr()) };
+    dbgmcu.idcode.read().dev_id().bits()
+}
+
+/// Silicon revision ID from DBGMCU_IDCODE, see the errata sheet for what each one means
+pub fn revision_id() -> u16 {
+    // Safety: Reading IDCODE has no side effects
+    let dbgmcu = unsafe { &(*pac::DBGMCU::ptr()) };
+    dbgmcu.idcode.read().rev_id().bits()
+}
+
+/// Everything this module can read, gathered up for logging
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub uid: Uid,
+    pub flash_size_kb: u16,
+    pub device_id: u16,
+    pub revision_id: u16,
+}
+
+impl DeviceInfo {
+    pub fn read() -> Self {
+        DeviceInfo {
+            uid: Uid::read(),
+            flash_size_kb: flash_size_kb(),
+            device_id: device_id(),
+            revision_id: revision_id(),
+        }
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "uid {} ({:08x}), {} KiB flash, device {:#05x} rev {:#06x}",
+            self.uid,
+            self.uid.short_id(),
+            self.flash_size_kb,
+            self.device_id,
+            self.revision_id
+        )
+    }
+}
@@ -0,0 +1,324 @@
+//! Minimal cooperative executor for running several board tasks at once
+//!
+//! [`Executor`] polls a fixed set of futures, such as an LED animation, button handling and sensor sampling,
+//! without needing an allocator. Each task is only polled again once its waker has been called,
+//! and when no task is ready [`Executor::run()`] sleeps with [`crate::wait_for_interrupt()`]
+//! until a board interrupt (EXTI, SysTick, timers...) wakes one up, see [`crate::asynch`].
+//!
+//! Tasks are borrowed rather than owned, so they can live on `main`'s stack, which never returns.
+//! Pin them with [`pin_task!`](crate::pin_task) before spawning.
+//!
+//! # Example
+//!
+//! ```
+//! let blink = async {
+//!     loop {
+//!         leds.ld3.toggle().ok();
+//!         delay.delay_ms(250).await;
+//!     }
+//! };
+//! let toggle = async {
+//!     loop {
+//!         button.wait_for_press(&mut button_int).await;
+//!         leds.ld10.toggle().ok();
+//!     }
+//! };
+//! pin_task!(blink, toggle);
+//!
+//! static READY: ReadySet = ReadySet::new();
+//! let mut executor = Executor::<2>::new(&READY);
+//! executor.spawn(blink).ok();
+//! executor.spawn(toggle).ok();
+//! executor.run();
+//! ```
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use cortex_m::interrupt;
+
+/// Maximum number of tasks, one per bit of the ready mask
+pub const MAX_TASKS: usize = 32;
+
+/// Tasks of one [`Executor`] that have been woken since they were last polled, one bit per task.
+///
+/// Wakers can outlive the executor (e.g. in a `static` `WakerSet`), so the set lives in a `static`
+/// that the executor borrows. Each executor needs its own set.
+///
+/// # Example
+///
+/// ```
+/// static READY: ReadySet = ReadySet::new();
+/// let mut executor = Executor::<4>::new(&READY);
+/// ```
+// Aligned so the low bits of its address are free to carry a task index, see `task_waker()`
+#[repr(align(32))]
+#[derive(Debug, Default)]
+pub struct ReadySet(AtomicU32);
+
+impl ReadySet {
+    pub const fn new() -> Self {
+        ReadySet(AtomicU32::new(0))
+    }
+
+    fn wake(&self, index: usize) {
+        self.0.fetch_or(1 << index, Ordering::AcqRel);
+    }
+
+    fn take(&self) -> u32 {
+        self.0.swap(0, Ordering::AcqRel)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.load(Ordering::Acquire) == 0
+    }
+}
+
+// The task's index is below `MAX_TASKS`, which is the alignment of `ReadySet`
+const INDEX_MASK: usize = MAX_TASKS - 1;
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake_task, wake_task, drop_waker);
+
+// The waker data is the address of the executor's `ReadySet` plus the task's index
+fn clone_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &VTABLE)
+}
+
+fn wake_task(data: *const ()) {
+    let set = (data as usize & !INDEX_MASK) as *const ReadySet;
+    // Safety: `task_waker()` only builds wakers from a `&'static ReadySet`, so it is still there
+    unsafe { (*set).wake(data as usize & INDEX_MASK) }
+}
+
+fn drop_waker(_: *const ()) {}
+
+fn task_waker(ready: &'static ReadySet, index: usize) -> Waker {
+    let data = (ready as *const ReadySet as usize | index) as *const ();
+    // Safety: The vtable functions only split the data back into the `'static` set and an index below `MAX_TASKS`
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+/// A pinned task that runs until it returns `()`
+pub type Task<'a> = Pin<&'a mut dyn Future<Output = ()>>;
+
+/// Pins futures on the stack so they can be handed to [`Executor::spawn()`],
+/// shadowing each variable with its pinned version.
+///
+/// # Example
+///
+/// ```
+/// let blink = blink(&mut leds, &delay);
+/// pin_task!(blink);
+/// executor.spawn(blink).ok();
+/// ```
+#[macro_export]
+macro_rules! pin_task {
+    ($($task:ident),* $(,)?) => {
+        $(
+            let mut $task = $task;
+            // Safety: The original is shadowed, so it can't be moved again after being pinned
+            #[allow(unused_mut)]
+            let mut $task = unsafe { core::pin::Pin::new_unchecked(&mut $task) };
+        )*
+    };
+}
+
+/// Runs up to `N` tasks cooperatively, see the [module documentation](self).
+pub struct Executor<'a, const N: usize> {
+    tasks: [Option<Task<'a>>; N],
+    ready: &'static ReadySet,
+}
+
+impl<'a, const N: usize> Executor<'a, N> {
+    // needed to build the array, `Task` isn't `Copy`
+    const EMPTY: Option<Task<'a>> = None;
+
+    /// Fails to compile for `N > MAX_TASKS`, since a ready set has one bit per task and [`MAX_TASKS`] bits in all.
+    /// Indexing past the end of an array stands in for `assert!`, see `EventQueue::NOT_EMPTY`.
+    const FITS_READY_SET: () = [()][(N > MAX_TASKS) as usize];
+
+    /// Creates an executor with room for `N` tasks, whose wakers mark them in `ready`.
+    /// Wakes left in `ready` by a previous executor are discarded.
+    ///
+    /// An executor with room for more than [`MAX_TASKS`] tasks is rejected at compile time.
+    pub fn new(ready: &'static ReadySet) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _: () = Self::FITS_READY_SET;
+        ready.take();
+        Executor { tasks: [Self::EMPTY; N], ready }
+    }
+
+    /// Adds a task, to be polled on the next [`Self::poll()`].
+    /// If all `N` slots are taken, the task is handed back.
+    pub fn spawn(&mut self, task: Task<'a>) -> Result<(), Task<'a>> {
+        match self.tasks.iter().position(Option::is_none) {
+            Some(index) => {
+                self.tasks[index] = Some(task);
+                task_waker(self.ready, index).wake();
+                Ok(())
+            }
+            None => Err(task),
+        }
+    }
+
+    /// Polls every task that has been woken since it was last polled,
+    /// and drops the tasks that complete.
+    /// Returns the number of tasks still running.
+    ///
+    /// This doesn't touch any hardware, so it can be driven on the host with any waker source.
+    pub fn poll(&mut self) -> usize {
+        let ready = self.ready.take();
+        for (index, slot) in self.tasks.iter_mut().enumerate() {
+            if ready & (1 << index) == 0 {
+                continue;
+            }
+            if let Some(task) = slot {
+                let waker = task_waker(self.ready, index);
+                let mut cx = Context::from_waker(&waker);
+                if let Poll::Ready(()) = task.as_mut().poll(&mut cx) {
+                    *slot = None;
+                }
+            }
+        }
+        self.running()
+    }
+
+    /// Number of tasks that haven't completed yet
+    pub fn running(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    /// True if a task has been woken and is waiting to be polled
+    pub fn is_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Runs the tasks until they have all completed,
+    /// sleeping until the next interrupt whenever none of them is ready.
+    pub fn run(&mut self) {
+        while self.poll() > 0 {
+            // With interrupts disabled, an interrupt that wakes a task after the check still ends the WFI,
+            // and is serviced once the critical section ends.
+            interrupt::free(|_| {
+                if !self.is_ready() {
+                    crate::wait_for_interrupt();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// Stands in for an interrupt: a task waits on it until the test fires it
+    #[derive(Default)]
+    struct Signal {
+        fired: Cell<bool>,
+        waker: Cell<Option<Waker>>,
+    }
+
+    impl Signal {
+        fn fire(&self) {
+            self.fired.set(true);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+
+        async fn wait(&self) {
+            crate::asynch::poll_fn(|cx| {
+                if self.fired.replace(false) {
+                    Poll::Ready(())
+                } else {
+                    self.waker.set(Some(cx.waker().clone()));
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    #[test]
+    fn only_woken_tasks_are_polled() {
+        static READY: ReadySet = ReadySet::new();
+        let (first, second) = (Signal::default(), Signal::default());
+        let (first_polls, second_polls) = (Cell::new(0), Cell::new(0));
+        let first_task = async {
+            for _ in 0..2 {
+                first_polls.set(first_polls.get() + 1);
+                first.wait().await;
+            }
+        };
+        let second_task = async {
+            loop {
+                second_polls.set(second_polls.get() + 1);
+                second.wait().await;
+            }
+        };
+        pin_task!(first_task, second_task);
+
+        let mut executor = Executor::<2>::new(&READY);
+        executor.spawn(first_task).ok();
+        executor.spawn(second_task).ok();
+        assert!(executor.is_ready());
+        assert_eq!(executor.poll(), 2);
+        assert_eq!((first_polls.get(), second_polls.get()), (1, 1));
+
+        // nothing fired, so nothing is polled
+        assert!(!executor.is_ready());
+        assert_eq!(executor.poll(), 2);
+        assert_eq!((first_polls.get(), second_polls.get()), (1, 1));
+
+        second.fire();
+        assert!(executor.is_ready());
+        assert_eq!(executor.poll(), 2);
+        assert_eq!((first_polls.get(), second_polls.get()), (1, 2));
+
+        first.fire();
+        assert_eq!(executor.poll(), 2);
+        assert_eq!((first_polls.get(), second_polls.get()), (2, 2));
+
+        // the first task completes on its second wake
+        first.fire();
+        assert_eq!(executor.poll(), 1);
+        assert_eq!(executor.running(), 1);
+        assert_eq!(second_polls.get(), 2);
+    }
+
+    #[test]
+    fn executors_keep_their_own_wakes() {
+        static FIRST: ReadySet = ReadySet::new();
+        static SECOND: ReadySet = ReadySet::new();
+        let signal = Signal::default();
+        let waiting = async { signal.wait().await };
+        let idle = async {};
+        pin_task!(waiting, idle);
+
+        let mut first = Executor::<1>::new(&FIRST);
+        first.spawn(waiting).ok();
+        assert_eq!(first.poll(), 1);
+
+        // creating and running another executor doesn't lose the first one's wakes
+        signal.fire();
+        let mut second = Executor::<1>::new(&SECOND);
+        second.spawn(idle).ok();
+        assert_eq!(second.poll(), 0);
+        assert!(first.is_ready());
+        assert_eq!(first.poll(), 0);
+    }
+
+    #[test]
+    fn waker_data_round_trips() {
+        static READY: ReadySet = ReadySet::new();
+        READY.take();
+        task_waker(&READY, MAX_TASKS - 1).wake_by_ref();
+        let waker = task_waker(&READY, 0);
+        let clone = waker.clone();
+        drop(waker);
+        clone.wake();
+        assert_eq!(READY.take(), 1 << (MAX_TASKS - 1) | 1);
+    }
+}
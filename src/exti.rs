@@ -0,0 +1,342 @@
+//! Manages the external interrupt (EXTI) lines used by the board's interrupt pins
+//!
+//! Each EXTI line 0-15 can be routed to the pin with the same number on exactly one GPIO port,
+//! e.g. EXTI0 is shared by PA0 (user button) and PE0 (gyro INT1).
+//! [`ExtiManager`] owns the `EXTI` and `SYSCFG` peripherals, tracks which lines have been claimed,
+//! and reports a conflict instead of silently re-routing a line that is already in use.
+//!
+//! # Example
+//!
+//! ```
+//! let mut syscfg = device_periphs.SYSCFG.constrain(&mut rcc.apb2);
+//! let mut exti = ExtiManager::new(device_periphs.EXTI, syscfg);
+//! let compass_drdy = exti.claim(Source::COMPASS_DRDY, TriggerMode::Rising).unwrap();
+//! // PE0 shares EXTI0 with the user button, so only one of them can be claimed
+//! let button = exti.claim(Source::USER_BUTTON, TriggerMode::Rising).unwrap();
+//! assert!(exti.claim(Source::GYRO_INT1, TriggerMode::Rising).is_err());
+//! ```
+use cortex_m::interrupt;
+use cortex_m::peripheral::NVIC;
+use stm32f3xx_hal::pac::{exti, Interrupt, EXTI};
+use stm32f3xx_hal::syscfg::SysCfg;
+
+/// Number of EXTI lines that can be routed to GPIO pins
+const GPIO_LINES: u8 = 16;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TriggerMode {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// GPIO port that an EXTI line is routed to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Port {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl Port {
+    /// Value of the port in the SYSCFG_EXTICR registers
+    fn bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Port> {
+        match bits {
+            0 => Some(Port::A),
+            1 => Some(Port::B),
+            2 => Some(Port::C),
+            3 => Some(Port::D),
+            4 => Some(Port::E),
+            5 => Some(Port::F),
+            _ => None,
+        }
+    }
+}
+
+/// A GPIO pin that can trigger an EXTI line
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Source {
+    port: Port,
+    line: u8,
+}
+
+impl Source {
+    /// User button (PA0)
+    pub const USER_BUTTON: Source = Source { port: Port::A, line: 0 };
+    /// L3GD20 gyro INT1 (PE0)
+    pub const GYRO_INT1: Source = Source { port: Port::E, line: 0 };
+    /// L3GD20 gyro DRDY/INT2 (PE1)
+    pub const GYRO_DRDY: Source = Source { port: Port::E, line: 1 };
+    /// LSM303DLHC magnetometer DRDY (PE2)
+    pub const COMPASS_DRDY: Source = Source { port: Port::E, line: 2 };
+    /// LSM303DLHC accelerometer INT1 (PE4)
+    pub const COMPASS_INT1: Source = Source { port: Port::E, line: 4 };
+    /// LSM303DLHC accelerometer INT2 (PE5)
+    pub const COMPASS_INT2: Source = Source { port: Port::E, line: 5 };
+
+    /// Any pin on the board, e.g. `Source::new(Port::C, 13)` for PC13.
+    /// Returns `None` if `pin` is greater than 15.
+    pub fn new(port: Port, pin: u8) -> Option<Source> {
+        if pin < GPIO_LINES {
+            Some(Source { port, line: pin })
+        } else {
+            None
+        }
+    }
+
+    pub fn port(self) -> Port {
+        self.port
+    }
+
+    /// The EXTI line, which is the same as the pin number
+    pub fn line(self) -> u8 {
+        self.line
+    }
+
+    /// NVIC interrupt that services this line.
+    /// Lines 5-9 and 10-15 share a single interrupt each.
+    pub fn interrupt(self) -> Interrupt {
+        match self.line {
+            0 => Interrupt::EXTI0,
+            1 => Interrupt::EXTI1,
+            2 => Interrupt::EXTI2_TSC,
+            3 => Interrupt::EXTI3,
+            4 => Interrupt::EXTI4,
+            5..=9 => Interrupt::EXTI9_5,
+            _ => Interrupt::EXTI15_10,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        1 << self.line
+    }
+
+    /// All of the lines that are serviced by the same NVIC interrupt as this one
+    fn shared_interrupt_mask(self) -> u32 {
+        match self.line {
+            5..=9 => 0b0000_0011_1110_0000,
+            10..=15 => 0b1111_1100_0000_0000,
+            _ => self.mask(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The line has already been claimed for a pin on `owner`
+    LineInUse { line: u8, owner: Port },
+}
+
+/// Owns the EXTI and SYSCFG peripherals and hands out [`ExtiLine`]s
+pub struct ExtiManager {
+    exti: EXTI,
+    syscfg: SysCfg,
+    claimed: u16,
+}
+
+impl ExtiManager {
+    /// Takes ownership of the EXTI and SYSCFG peripherals.
+    /// Taking the constrained [`SysCfg`] guarantees the SYSCFG clock is enabled.
+    pub fn new(exti: EXTI, syscfg: SysCfg) -> Self {
+        ExtiManager { exti, syscfg, claimed: 0 }
+    }
+
+    /// Routes the line for `source` to its port, configures the trigger and enables the interrupt.
+    ///
+    /// Returns [`Error::LineInUse`] if the line has already been claimed, even for the same pin,
+    /// because each line can only have a single owner.
+    pub fn claim(&mut self, source: Source, mode: TriggerMode) -> Result<ExtiLine, Error> {
+        if self.claimed & source.mask() as u16 != 0 {
+            return Err(Error::LineInUse {
+                line: source.line,
+                owner: self.routed_port(source.line),
+            });
+        }
+        self.claimed |= source.mask() as u16;
+        self.route(source);
+        Ok(ExtiLine::configure(source, mode))
+    }
+
+    /// Disables the line and makes it available to be claimed again
+    pub fn release(&mut self, mut line: ExtiLine) {
+        line.disable();
+        modify_exti(|exti| {
+            exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !line.source.mask()) });
+            exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !line.source.mask()) });
+        });
+        line.clear();
+        self.claimed &= !(line.source.mask() as u16);
+    }
+
+    /// True if the line has been claimed and not yet released
+    pub fn is_claimed(&self, line: u8) -> bool {
+        line < GPIO_LINES && self.claimed & (1 << line) != 0
+    }
+
+    /// Releases the EXTI and SYSCFG peripherals.
+    /// Outstanding `ExtiLine`s keep working, but are no longer tracked.
+    pub fn free(self) -> (EXTI, SysCfg) {
+        (self.exti, self.syscfg)
+    }
+
+    fn route(&mut self, source: Source) {
+        let shift = u32::from(source.line % 4) * 4;
+        let clear = !(0b1111 << shift);
+        let port = source.port.bits() << shift;
+        // Safety: Only the 4 bits for this line are changed, and any port value 0-5 is valid
+        match source.line / 4 {
+            0 => self.syscfg.exticr1.modify(|r, w| unsafe { w.bits(r.bits() & clear | port) }),
+            1 => self.syscfg.exticr2.modify(|r, w| unsafe { w.bits(r.bits() & clear | port) }),
+            2 => self.syscfg.exticr3.modify(|r, w| unsafe { w.bits(r.bits() & clear | port) }),
+            _ => self.syscfg.exticr4.modify(|r, w| unsafe { w.bits(r.bits() & clear | port) }),
+        }
+    }
+
+    fn routed_port(&self, line: u8) -> Port {
+        let bits = match line / 4 {
+            0 => self.syscfg.exticr1.read().bits(),
+            1 => self.syscfg.exticr2.read().bits(),
+            2 => self.syscfg.exticr3.read().bits(),
+            _ => self.syscfg.exticr4.read().bits(),
+        };
+        let shift = u32::from(line % 4) * 4;
+        // the register only holds 0-5 for ports A-F
+        Port::from_bits((bits >> shift) & 0b1111).unwrap_or(Port::A)
+    }
+}
+
+/// Handle to a single claimed EXTI line, returned by [`ExtiManager::claim()`].
+///
+/// Move it to wherever the line's interrupt handler can reach it;
+/// the handler has to call [`Self::clear()`] or the interrupt will continue to fire.
+#[derive(Debug)]
+pub struct ExtiLine {
+    source: Source,
+}
+
+impl ExtiLine {
+    /// Configures the trigger for a line that has already been routed and enables it
+    fn configure(source: Source, mode: TriggerMode) -> Self {
+        let mut line = ExtiLine { source };
+        line.set_trigger(mode);
+        // don't fire immediately for an edge that happened before we were enabled
+        line.clear();
+        line.enable();
+        line
+    }
+
+    /// The pin that triggers this line
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    /// Unmasks the line in the EXTI and its interrupt in the NVIC
+    pub fn enable(&mut self) {
+        let mask = self.source.mask();
+        crate::asynch::exti_mask_changed(mask);
+        modify_exti(|exti| exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | mask) }));
+        unsafe {
+            NVIC::unmask(self.source.interrupt());
+        }
+    }
+
+    /// Masks the line in the EXTI.
+    /// The NVIC interrupt is also masked, unless it is shared with another line that is still enabled.
+    /// The trigger configuration is kept, so it can be re-enabled with [`Self::enable()`].
+    pub fn disable(&mut self) {
+        let source = self.source;
+        crate::asynch::exti_mask_changed(source.mask());
+        let still_enabled = modify_exti(|exti| {
+            exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !source.mask()) });
+            exti.imr1.read().bits() & source.shared_interrupt_mask()
+        });
+        if still_enabled == 0 {
+            NVIC::mask(source.interrupt());
+        }
+    }
+
+    /// Also raises an event on every trigger, which wakes the core from `WFE`
+    /// (see [`crate::power::WakeOn::Event`]) without running an interrupt handler.
+    pub fn enable_event(&mut self) {
+        let mask = self.source.mask();
+        modify_exti(|exti| exti.emr1.modify(|r, w| unsafe { w.bits(r.bits() | mask) }));
+    }
+
+    /// Stops raising events for the line
+    pub fn disable_event(&mut self) {
+        let mask = self.source.mask();
+        modify_exti(|exti| exti.emr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) }));
+    }
+
+    /// True if an edge on the line is waiting to be serviced
+    pub fn is_pending(&self) -> bool {
+        // Safety: Reading PR1 has no side effects
+        let exti = unsafe { &(*EXTI::ptr()) };
+        exti.pr1.read().bits() & self.source.mask() != 0
+    }
+
+    /// Clears the pending bit for the line.
+    /// Must be called from the line's interrupt handler, or it will continue to fire.
+    pub fn clear(&mut self) {
+        unsafe {
+            // Safety: PR1 is write 1 to clear, so this single write only affects our line
+            let exti = &(*EXTI::ptr());
+            exti.pr1.write(|w| w.bits(self.source.mask()))
+        }
+    }
+
+    /// Completes on the next trigger of the line, or right away if one is already pending.
+    /// The line is unmasked while waiting, and left masked or unmasked the way it was before.
+    ///
+    /// The line's `EXTIx` handler must call [`crate::asynch::on_exti_interrupt()`] to wake the task.
+    pub async fn wait(&mut self) {
+        crate::asynch::exti_edge(self.source.mask()).await
+    }
+
+    /// Changes which edges trigger the interrupt
+    pub fn set_trigger(&mut self, mode: TriggerMode) {
+        let mask = self.source.mask();
+        let (rising, falling) = match mode {
+            TriggerMode::Rising => (mask, 0),
+            TriggerMode::Falling => (0, mask),
+            TriggerMode::Both => (mask, mask),
+        };
+        modify_exti(|exti| {
+            exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask | rising) });
+            exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask | falling) });
+        });
+    }
+
+    /// Current NVIC priority of the line's interrupt
+    pub fn priority(&self) -> u8 {
+        NVIC::get_priority(self.source.interrupt())
+    }
+
+    /// Sets the NVIC priority of the line's interrupt.
+    /// Lower values are higher priority, and the STM32F3 only implements the top 4 bits.
+    /// Lines that share an interrupt also share its priority.
+    ///
+    /// # Safety
+    ///
+    /// Changing priority levels can break priority-based critical sections (see [`NVIC::set_priority()`]).
+    pub unsafe fn set_priority(&mut self, nvic: &mut NVIC, priority: u8) {
+        nvic.set_priority(self.source.interrupt(), priority)
+    }
+}
+
+/// The EXTI registers are shared by every line,
+/// so read-modify-writes happen in a critical section to avoid racing other handlers.
+pub(crate) fn modify_exti<T, F: FnOnce(&exti::RegisterBlock) -> T>(f: F) -> T {
+    interrupt::free(|_| {
+        // Safety: Callers only touch the bits for their own line, and the critical section keeps the RMW atomic
+        let exti = unsafe { &(*EXTI::ptr()) };
+        f(exti)
+    })
+}
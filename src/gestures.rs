@@ -0,0 +1,328 @@
+//! Button and tilt gestures, and the keys and media keys they map to
+//!
+//! This is plain logic, independent of the hardware, and doesn't need the `usb` feature.
+//! With it, `usb::keyboard::UsbKeyboard` sends the resulting actions to a host.
+//!
+//! - [`Gesture::from_button()`] picks the gestures out of [`ButtonEvent`]s.
+//! - [`TiltGestures`] reports a gesture each time the board is tilted past a threshold and brought back.
+//! - [`KeyMap`] assigns an [`Action`], a key or a media key, to each [`Gesture`].
+//!
+//! # Example
+//!
+//! ```
+//! let keymap = KeyMap::presenter();
+//! let mut tilt = TiltGestures::default();
+//! if let Some(action) = tilt.update(compass.accel_norm().unwrap()).and_then(|gesture| keymap.action(gesture)) {
+//!     keyboard.tap(action);
+//! }
+//! ```
+use accelerometer::vector::F32x3;
+
+use crate::button::events::ButtonEvent;
+
+/// Keyboard usage codes, from the HID Usage Tables' Keyboard/Keypad page
+pub mod key {
+    pub const A: u8 = 0x04;
+    pub const B: u8 = 0x05;
+    pub const W: u8 = 0x1a;
+    pub const ENTER: u8 = 0x28;
+    pub const ESCAPE: u8 = 0x29;
+    pub const SPACE: u8 = 0x2c;
+    pub const F5: u8 = 0x3e;
+    pub const PAGE_UP: u8 = 0x4b;
+    pub const PAGE_DOWN: u8 = 0x4e;
+    pub const RIGHT_ARROW: u8 = 0x4f;
+    pub const LEFT_ARROW: u8 = 0x50;
+    pub const DOWN_ARROW: u8 = 0x51;
+    pub const UP_ARROW: u8 = 0x52;
+}
+
+/// Modifier key bits
+pub mod modifier {
+    pub const LEFT_CTRL: u8 = 1 << 0;
+    pub const LEFT_SHIFT: u8 = 1 << 1;
+    pub const LEFT_ALT: u8 = 1 << 2;
+    pub const LEFT_GUI: u8 = 1 << 3;
+}
+
+/// Consumer control usage codes, from the HID Usage Tables' Consumer page
+pub mod media {
+    pub const NEXT_TRACK: u16 = 0xb5;
+    pub const PREVIOUS_TRACK: u16 = 0xb6;
+    pub const STOP: u16 = 0xb7;
+    pub const PLAY_PAUSE: u16 = 0xcd;
+    pub const MUTE: u16 = 0xe2;
+    pub const VOLUME_UP: u16 = 0xe9;
+    pub const VOLUME_DOWN: u16 = 0xea;
+}
+
+/// What to send for a gesture
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// A key from [`key`], held together with the [`modifier`] keys
+    Key { modifiers: u8, key: u8 },
+    /// A media key from [`media`]
+    Media(u16),
+}
+
+impl Action {
+    /// A key without modifiers
+    pub const fn key(key: u8) -> Self {
+        Action::Key { modifiers: 0, key }
+    }
+}
+
+/// Inputs that can be mapped to an [`Action`].
+///
+/// The tilt directions are along the accelerometer's axes:
+/// right and left are its positive and negative X axis, forward and back its positive and negative Y axis.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Gesture {
+    Click,
+    DoubleClick,
+    LongPress,
+    TiltRight,
+    TiltLeft,
+    TiltForward,
+    TiltBack,
+}
+
+impl Gesture {
+    /// The gesture for a button event, if it is one
+    pub fn from_button(event: ButtonEvent) -> Option<Gesture> {
+        match event {
+            ButtonEvent::Click => Some(Gesture::Click),
+            ButtonEvent::DoubleClick => Some(Gesture::DoubleClick),
+            ButtonEvent::LongPress => Some(Gesture::LongPress),
+            ButtonEvent::Pressed | ButtonEvent::Released | ButtonEvent::Held(_) => None,
+        }
+    }
+}
+
+/// The [`Action`] for each [`Gesture`], `None` for gestures that are ignored
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct KeyMap {
+    pub click: Option<Action>,
+    pub double_click: Option<Action>,
+    pub long_press: Option<Action>,
+    pub tilt_right: Option<Action>,
+    pub tilt_left: Option<Action>,
+    pub tilt_forward: Option<Action>,
+    pub tilt_back: Option<Action>,
+}
+
+impl KeyMap {
+    /// Slide controls: click for the next slide, double click for the previous one,
+    /// long press to blank the screen, and tilting right or left to step through the slides too.
+    pub fn presenter() -> Self {
+        KeyMap {
+            click: Some(Action::key(key::RIGHT_ARROW)),
+            double_click: Some(Action::key(key::LEFT_ARROW)),
+            long_press: Some(Action::key(key::B)),
+            tilt_right: Some(Action::key(key::RIGHT_ARROW)),
+            tilt_left: Some(Action::key(key::LEFT_ARROW)),
+            tilt_forward: None,
+            tilt_back: None,
+        }
+    }
+
+    /// Media controls: click to play or pause, double click for the next track, long press to mute,
+    /// tilting right or left to skip tracks and forward or back to change the volume.
+    pub fn media() -> Self {
+        KeyMap {
+            click: Some(Action::Media(media::PLAY_PAUSE)),
+            double_click: Some(Action::Media(media::NEXT_TRACK)),
+            long_press: Some(Action::Media(media::MUTE)),
+            tilt_right: Some(Action::Media(media::NEXT_TRACK)),
+            tilt_left: Some(Action::Media(media::PREVIOUS_TRACK)),
+            tilt_forward: Some(Action::Media(media::VOLUME_UP)),
+            tilt_back: Some(Action::Media(media::VOLUME_DOWN)),
+        }
+    }
+
+    /// The action mapped to `gesture`
+    pub fn action(&self, gesture: Gesture) -> Option<Action> {
+        match gesture {
+            Gesture::Click => self.click,
+            Gesture::DoubleClick => self.double_click,
+            Gesture::LongPress => self.long_press,
+            Gesture::TiltRight => self.tilt_right,
+            Gesture::TiltLeft => self.tilt_left,
+            Gesture::TiltForward => self.tilt_forward,
+            Gesture::TiltBack => self.tilt_back,
+        }
+    }
+}
+
+/// Detects tilt gestures from accelerometer readings in g.
+///
+/// A gesture is reported once when the tilt along an axis goes past `threshold`,
+/// and the next one only after the board is back within `rearm` of level, so holding a tilt doesn't repeat it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TiltGestures {
+    /// Tilt in g that triggers a gesture
+    pub threshold: f32,
+    /// Tilt in g that all axes have to be back within before another gesture can trigger
+    pub rearm: f32,
+    armed: bool,
+}
+
+impl TiltGestures {
+    pub fn new(threshold: f32, rearm: f32) -> Self {
+        TiltGestures { threshold, rearm, armed: true }
+    }
+
+    /// Feeds a reading, e.g. from `Compass::accel_norm()`, returning a gesture if one was completed
+    pub fn update(&mut self, accel: F32x3) -> Option<Gesture> {
+        if !self.armed {
+            self.armed = magnitude(accel.x) < self.rearm && magnitude(accel.y) < self.rearm;
+            return None;
+        }
+
+        // the axis tilted furthest wins if both are past the threshold
+        let (x, y) = (accel.x, accel.y);
+        let x_further = magnitude(x) >= magnitude(y);
+        let gesture = if x_further && x >= self.threshold {
+            Gesture::TiltRight
+        } else if x_further && x <= -self.threshold {
+            Gesture::TiltLeft
+        } else if !x_further && y >= self.threshold {
+            Gesture::TiltForward
+        } else if !x_further && y <= -self.threshold {
+            Gesture::TiltBack
+        } else {
+            return None;
+        };
+        self.armed = false;
+        Some(gesture)
+    }
+}
+
+fn magnitude(value: f32) -> f32 {
+    if value < 0.0 {
+        -value
+    } else {
+        value
+    }
+}
+
+impl Default for TiltGestures {
+    /// Triggers at about 35° and re-arms below about 12°
+    fn default() -> Self {
+        TiltGestures::new(0.57, 0.2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accel(x: f32, y: f32) -> F32x3 {
+        F32x3::new(x, y, 1.0)
+    }
+
+    #[test]
+    fn button_gestures() {
+        assert_eq!(Gesture::from_button(ButtonEvent::Click), Some(Gesture::Click));
+        assert_eq!(Gesture::from_button(ButtonEvent::DoubleClick), Some(Gesture::DoubleClick));
+        assert_eq!(Gesture::from_button(ButtonEvent::LongPress), Some(Gesture::LongPress));
+        assert_eq!(Gesture::from_button(ButtonEvent::Pressed), None);
+        assert_eq!(Gesture::from_button(ButtonEvent::Released), None);
+        assert_eq!(Gesture::from_button(ButtonEvent::Held(1_000)), None);
+    }
+
+    #[test]
+    fn tilt_gestures_in_every_direction() {
+        let cases = [
+            (accel(0.6, 0.0), Gesture::TiltRight),
+            (accel(-0.6, 0.0), Gesture::TiltLeft),
+            (accel(0.0, 0.6), Gesture::TiltForward),
+            (accel(0.0, -0.6), Gesture::TiltBack),
+        ];
+        for &(reading, gesture) in cases.iter() {
+            let mut tilt = TiltGestures::default();
+            assert_eq!(tilt.update(reading), Some(gesture));
+        }
+    }
+
+    #[test]
+    fn small_tilts_are_ignored() {
+        let mut tilt = TiltGestures::default();
+        assert_eq!(tilt.update(accel(0.0, 0.0)), None);
+        assert_eq!(tilt.update(accel(0.56, -0.56)), None);
+        assert_eq!(tilt.update(accel(0.57, 0.0)), Some(Gesture::TiltRight));
+    }
+
+    #[test]
+    fn axis_tilted_furthest_wins() {
+        let mut tilt = TiltGestures::default();
+        assert_eq!(tilt.update(accel(0.6, -0.7)), Some(Gesture::TiltBack));
+        tilt.update(accel(0.0, 0.0));
+        assert_eq!(tilt.update(accel(-0.8, 0.7)), Some(Gesture::TiltLeft));
+    }
+
+    #[test]
+    fn holding_a_tilt_doesnt_repeat() {
+        let mut tilt = TiltGestures::default();
+        assert_eq!(tilt.update(accel(0.7, 0.0)), Some(Gesture::TiltRight));
+        assert_eq!(tilt.update(accel(0.7, 0.0)), None);
+        assert_eq!(tilt.update(accel(0.9, 0.0)), None);
+        // another direction doesn't trigger either until the board is back near level
+        assert_eq!(tilt.update(accel(0.0, 0.9)), None);
+    }
+
+    #[test]
+    fn rearms_only_near_level() {
+        let mut tilt = TiltGestures::default();
+        assert_eq!(tilt.update(accel(0.7, 0.0)), Some(Gesture::TiltRight));
+
+        // between the re-arm level and the threshold is still too far
+        assert_eq!(tilt.update(accel(0.3, 0.0)), None);
+        assert_eq!(tilt.update(accel(0.7, 0.0)), None);
+        // both axes have to come back
+        assert_eq!(tilt.update(accel(0.1, 0.3)), None);
+        assert_eq!(tilt.update(accel(0.7, 0.0)), None);
+
+        // the reading that re-arms doesn't trigger, the next one past the threshold does
+        assert_eq!(tilt.update(accel(0.1, -0.1)), None);
+        assert_eq!(tilt.update(accel(0.7, 0.0)), Some(Gesture::TiltRight));
+    }
+
+    #[test]
+    fn presenter_keys() {
+        let keymap = KeyMap::presenter();
+        let right = Some(Action::key(key::RIGHT_ARROW));
+        let left = Some(Action::key(key::LEFT_ARROW));
+        assert_eq!(keymap.action(Gesture::Click), right);
+        assert_eq!(keymap.action(Gesture::DoubleClick), left);
+        assert_eq!(keymap.action(Gesture::LongPress), Some(Action::key(key::B)));
+        assert_eq!(keymap.action(Gesture::TiltRight), right);
+        assert_eq!(keymap.action(Gesture::TiltLeft), left);
+        assert_eq!(keymap.action(Gesture::TiltForward), None);
+        assert_eq!(keymap.action(Gesture::TiltBack), None);
+    }
+
+    #[test]
+    fn media_keys() {
+        let keymap = KeyMap::media();
+        let cases = [
+            (Gesture::Click, media::PLAY_PAUSE),
+            (Gesture::DoubleClick, media::NEXT_TRACK),
+            (Gesture::LongPress, media::MUTE),
+            (Gesture::TiltRight, media::NEXT_TRACK),
+            (Gesture::TiltLeft, media::PREVIOUS_TRACK),
+            (Gesture::TiltForward, media::VOLUME_UP),
+            (Gesture::TiltBack, media::VOLUME_DOWN),
+        ];
+        for &(gesture, usage) in cases.iter() {
+            assert_eq!(keymap.action(gesture), Some(Action::Media(usage)));
+        }
+    }
+
+    #[test]
+    fn default_keymap_ignores_everything() {
+        let keymap = KeyMap::default();
+        assert_eq!(keymap.action(Gesture::Click), None);
+        assert_eq!(keymap.action(Gesture::TiltBack), None);
+    }
+}
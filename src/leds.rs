@@ -1,14 +1,20 @@
 //! Provides access to User LEDs LD3-LD10
 use stm32f3xx_hal::gpio::gpioe;
 use stm32f3xx_hal::gpio::{Output, PushPull};
+use stm32f3xx_hal::pac;
 
-use switch_hal::{ActiveHigh, IntoSwitch, OutputSwitch, Switch};
+use switch_hal::{
+    ActiveHigh, IntoSwitch, OutputSwitch, StatefulOutputSwitch, Switch, ToggleableOutputSwitch,
+};
 
+use core::iter::{Copied, FusedIterator, Zip};
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Deref, DerefMut, Index, IndexMut, Not};
+use core::ptr::addr_of_mut;
 use core::slice::Iter;
-use core::iter::FusedIterator;
 
 /// LED compass direction as noted on the board
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Direction
 {
     North,
@@ -21,26 +27,346 @@ pub enum Direction
     NorthWest,
 }
 
+const DIRECTION_COUNT: usize = 8;
+
+static DIRECTIONS: [Direction; DIRECTION_COUNT] = [
+    Direction::North,
+    Direction::NorthEast,
+    Direction::East,
+    Direction::SouthEast,
+    Direction::South,
+    Direction::SouthWest,
+    Direction::West,
+    Direction::NorthWest
+];
+
+/// Angle covered by each direction on the compass rose
+const DEGREES_PER_DIRECTION: f32 = 360.0 / DIRECTION_COUNT as f32;
+
+/// tan(22.5°), the slope of the boundary between a cardinal and an ordinal direction
+const TAN_HALF_SECTOR: f32 = 0.414_213_57;
+
 impl Direction {
     /// Provides an iterator starting with North
     /// and moving clockwise around the compass
     /// e.g. N -> NE -> E, etc.
     pub fn iter() -> Iter<'static, Direction> {
-        static DIRECTIONS: [Direction; 8] = [
-            Direction::North,
-            Direction::NorthEast,
-            Direction::East,
-            Direction::SouthEast,
-            Direction::South,
-            Direction::SouthWest,
-            Direction::West,
-            Direction::NorthWest
-        ];
         DIRECTIONS.iter()
     }
+
+    /// Position of the direction on the compass rose,
+    /// where North is 0 and the index increments clockwise up to NorthWest (7).
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Direction at the given position on the compass rose (see [`Self::index()`]).
+    /// Returns `None` if `index` is greater than 7.
+    pub fn from_index(index: usize) -> Option<Direction> {
+        DIRECTIONS.get(index).copied()
+    }
+
+    /// Bearing of the direction in degrees, measured clockwise from North.
+    /// e.g. North is 0°, East is 90°, and NorthWest is 315°.
+    pub fn to_degrees(self) -> f32 {
+        self.index() as f32 * DEGREES_PER_DIRECTION
+    }
+
+    /// Nearest direction to a bearing given in degrees clockwise from North.
+    ///
+    /// Any angle is accepted; values outside of 0..360 wrap around the compass.
+    /// Each direction covers ±22.5° around its own bearing,
+    /// and a bearing exactly on a boundary belongs to the next direction clockwise.
+    /// NaN and infinite angles have no bearing and give North.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// assert!(Direction::from_degrees(80.0) == Direction::East);
+    /// assert!(Direction::from_degrees(-30.0) == Direction::NorthWest);
+    /// ```
+    pub fn from_degrees(degrees: f32) -> Direction {
+        let mut normalized = degrees % 360.0;
+        if normalized < 0.0 {
+            normalized += 360.0;
+        }
+        // normalized is never negative, so truncating is the same as flooring
+        let sector = ((normalized + DEGREES_PER_DIRECTION / 2.0) / DEGREES_PER_DIRECTION) as usize;
+        DIRECTIONS[sector % DIRECTION_COUNT]
+    }
+
+    /// Nearest direction to a 2D vector, such as a tilt or heading reading,
+    /// where `x` points East and `y` points North.
+    /// Returns `None` for a zero length vector or a NaN component, as they have no direction.
+    ///
+    /// A vector exactly on a sector boundary belongs to the cardinal direction (N, E, S or W).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// assert!(Direction::from_vector(1.0, 1.0) == Some(Direction::NorthEast));
+    /// assert!(Direction::from_vector(0.0, -5.0) == Some(Direction::South));
+    /// ```
+    pub fn from_vector(x: f32, y: f32) -> Option<Direction> {
+        if (x == 0.0 && y == 0.0) || x.is_nan() || y.is_nan() {
+            return None;
+        }
+
+        // No trig functions in `core`, so compare slopes against the sector boundaries instead
+        let abs_x = if x < 0.0 { -x } else { x };
+        let abs_y = if y < 0.0 { -y } else { y };
+
+        let direction = if abs_x <= abs_y * TAN_HALF_SECTOR {
+            if y > 0.0 { Direction::North } else { Direction::South }
+        } else if abs_y <= abs_x * TAN_HALF_SECTOR {
+            if x > 0.0 { Direction::East } else { Direction::West }
+        } else {
+            match (x > 0.0, y > 0.0) {
+                (true, true) => Direction::NorthEast,
+                (true, false) => Direction::SouthEast,
+                (false, false) => Direction::SouthWest,
+                (false, true) => Direction::NorthWest,
+            }
+        };
+        Some(direction)
+    }
+
+    /// Direction `steps` positions clockwise from this one.
+    /// e.g. `Direction::North.rotate_cw(2)` is East.
+    pub fn rotate_cw(self, steps: usize) -> Direction {
+        DIRECTIONS[(self.index() + steps % DIRECTION_COUNT) % DIRECTION_COUNT]
+    }
+
+    /// Direction `steps` positions counter clockwise from this one.
+    /// e.g. `Direction::North.rotate_ccw(2)` is West.
+    pub fn rotate_ccw(self, steps: usize) -> Direction {
+        self.rotate_cw(DIRECTION_COUNT - steps % DIRECTION_COUNT)
+    }
+
+    /// Direction on the opposite side of the compass.
+    /// e.g. North -> South, NorthEast -> SouthWest
+    pub fn opposite(self) -> Direction {
+        self.rotate_cw(DIRECTION_COUNT / 2)
+    }
+
+    /// The adjacent directions on either side of this one,
+    /// as `[counter clockwise, clockwise]`.
+    /// e.g. North -> [NorthWest, NorthEast]
+    pub fn neighbors(self) -> [Direction; 2] {
+        [self.rotate_ccw(1), self.rotate_cw(1)]
+    }
+}
+
+/// LED color as fitted on the board.
+/// Each color is used by the two leds on opposite sides of the compass.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+    /// LD3 (N) and LD10 (S)
+    Red,
+    /// LD4 (NW) and LD9 (SE)
+    Blue,
+    /// LD5 (NE) and LD8 (SW)
+    Orange,
+    /// LD6 (W) and LD7 (E)
+    Green,
+}
+
+impl Color {
+    /// Provides an iterator over all of the led colors
+    pub fn iter() -> Iter<'static, Color> {
+        static COLORS: [Color; 4] = [Color::Red, Color::Orange, Color::Green, Color::Blue];
+        COLORS.iter()
+    }
+
+    /// The directions of the two leds with this color, in clockwise order starting from North
+    pub fn directions(self) -> [Direction; 2] {
+        match self {
+            Color::Red => [Direction::North, Direction::South],
+            Color::Orange => [Direction::NorthEast, Direction::SouthWest],
+            Color::Green => [Direction::East, Direction::West],
+            Color::Blue => [Direction::SouthEast, Direction::NorthWest],
+        }
+    }
+}
+
+impl Direction {
+    /// Color of the led at this direction
+    pub fn color(self) -> Color {
+        // Opposite leds share a color and Red, Orange, Green, Blue
+        // go clockwise from North, so the color repeats every 4 steps.
+        match self.index() % 4 {
+            0 => Color::Red,
+            1 => Color::Orange,
+            2 => Color::Green,
+            _ => Color::Blue,
+        }
+    }
+}
+
+/// A set of leds, addressed by their direction on the compass.
+///
+/// Bit `n` of the mask corresponds to the direction with [`Direction::index()`] `n`,
+/// so bit 0 is North and bit 7 is NorthWest.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LedMask(u8);
+
+impl LedMask {
+    /// A mask with no leds selected
+    pub const NONE: LedMask = LedMask(0);
+    /// A mask with every led selected
+    pub const ALL: LedMask = LedMask(0xFF);
+
+    /// Creates a mask from its raw bits, see [`Self::bits()`]
+    pub const fn from_bits(bits: u8) -> Self {
+        LedMask(bits)
+    }
+
+    /// Raw bits of the mask, where bit 0 is North and bits increment clockwise
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// True if the led for `direction` is part of the mask
+    pub fn contains(self, direction: Direction) -> bool {
+        self.0 & LedMask::from(direction).0 != 0
+    }
+
+    /// Adds the led for `direction` to the mask
+    pub fn insert(&mut self, direction: Direction) {
+        self.0 |= LedMask::from(direction).0;
+    }
+
+    /// Removes the led for `direction` from the mask
+    pub fn remove(&mut self, direction: Direction) {
+        self.0 &= !LedMask::from(direction).0;
+    }
+
+    /// True if no leds are selected
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Number of leds selected
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Iterates over the directions in the mask, starting at North and moving clockwise
+    pub fn directions(self) -> impl Iterator<Item = Direction> {
+        Direction::iter().copied().filter(move |d| self.contains(*d))
+    }
+}
+
+impl From<Direction> for LedMask {
+    fn from(direction: Direction) -> Self {
+        LedMask(1 << direction.index())
+    }
+}
+
+/// Selects both leds of the given color
+///
+/// # Example
+///
+/// Select only the red led in the northern half of the compass
+///
+/// ```
+/// let north = LedMask::from(Direction::NorthWest) | Direction::North.into() | Direction::NorthEast.into();
+/// let red_north = LedMask::from(Color::Red) & north;
+/// ```
+impl From<Color> for LedMask {
+    fn from(color: Color) -> Self {
+        let [first, second] = color.directions();
+        LedMask::from(first) | LedMask::from(second)
+    }
+}
+
+impl BitOr for LedMask {
+    type Output = LedMask;
+    fn bitor(self, rhs: LedMask) -> LedMask {
+        LedMask(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for LedMask {
+    type Output = LedMask;
+    fn bitand(self, rhs: LedMask) -> LedMask {
+        LedMask(self.0 & rhs.0)
+    }
+}
+
+impl Not for LedMask {
+    type Output = LedMask;
+    fn not(self) -> LedMask {
+        LedMask(!self.0)
+    }
+}
+
+/// A single user LED, e.g. to move one into an RTIC resource on its own
+///
+/// Only [`Leds`] can create one, so a `Led` is always one of PE8-PE15.
+pub struct Led(Switch<gpioe::PEx<Output<PushPull>>, ActiveHigh>);
+
+impl Led {
+    fn new(pin: gpioe::PEx<Output<PushPull>>) -> Self {
+        Led(pin.into_active_high_switch())
+    }
+}
+
+impl OutputSwitch for Led {
+    type Error = core::convert::Infallible;
+    fn on(&mut self) -> Result<(), Self::Error> {
+        self.0.on()
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error> {
+        self.0.off()
+    }
+}
+
+impl ToggleableOutputSwitch for Led {
+    type Error = core::convert::Infallible;
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.0.toggle()
+    }
 }
 
-type Led = Switch<gpioe::PEx<Output<PushPull>>, ActiveHigh>;
+impl StatefulOutputSwitch for Led {
+    type Error = core::convert::Infallible;
+    fn is_on(&mut self) -> Result<bool, Self::Error> {
+        self.0.is_on()
+    }
+
+    fn is_off(&mut self) -> Result<bool, Self::Error> {
+        self.0.is_off()
+    }
+}
+
+/// The GPIOE pins PE8-PE15 released by [`Leds::free()`], in pin order.
+pub type LedPins = (
+    gpioe::PE8<Output<PushPull>>,
+    gpioe::PE9<Output<PushPull>>,
+    gpioe::PE10<Output<PushPull>>,
+    gpioe::PE11<Output<PushPull>>,
+    gpioe::PE12<Output<PushPull>>,
+    gpioe::PE13<Output<PushPull>>,
+    gpioe::PE14<Output<PushPull>>,
+    gpioe::PE15<Output<PushPull>>,
+);
+
+/// Consumes a led and returns its pin with the pin number restored to the type.
+///
+/// The HAL has no way to undo `downgrade()`, but typed pins are zero sized markers,
+/// so we can recreate one once the led that owned the physical pin has been consumed.
+/// `transmute` fails to compile if the pin type is ever not zero sized.
+macro_rules! into_typed_pin {
+    ($led:expr, $pin:ident) => {{
+        let _erased: gpioe::PEx<Output<PushPull>> = $led.0.into_pin();
+        // Safety: `$led` owned this pin and has just been consumed,
+        // so this is the only handle to it. A `Led` can only be created by `Leds::new()`,
+        // so even if the fields were swapped around, `Leds` still holds exactly PE8-PE15 once each.
+        unsafe { core::mem::transmute::<(), gpioe::$pin<Output<PushPull>>>(()) }
+    }};
+}
 
 pub struct Leds {
     /// North
@@ -63,6 +389,7 @@ pub struct Leds {
 
 impl Leds {
     /// Initializes the user LEDs to OFF
+    #[allow(clippy::too_many_arguments)]
     pub fn new<PE8Mode, PE9Mode, PE10Mode, PE11Mode, PE12Mode, PE13Mode, PE14Mode, PE15Mode>(
         pe8: gpioe::PE8<PE8Mode>,
         pe9: gpioe::PE9<PE9Mode>,
@@ -76,38 +403,14 @@ impl Leds {
         otyper: &mut gpioe::OTYPER,
     ) -> Self {
         let mut leds = Leds {
-            ld3: pe9
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld4: pe8
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld5: pe10
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld6: pe15
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld7: pe11
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld8: pe14
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld9: pe12
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
-            ld10: pe13
-                .into_push_pull_output(moder, otyper)
-                .downgrade()
-                .into_active_high_switch(),
+            ld3: Led::new(pe9.into_push_pull_output(moder, otyper).downgrade()),
+            ld4: Led::new(pe8.into_push_pull_output(moder, otyper).downgrade()),
+            ld5: Led::new(pe10.into_push_pull_output(moder, otyper).downgrade()),
+            ld6: Led::new(pe15.into_push_pull_output(moder, otyper).downgrade()),
+            ld7: Led::new(pe11.into_push_pull_output(moder, otyper).downgrade()),
+            ld8: Led::new(pe14.into_push_pull_output(moder, otyper).downgrade()),
+            ld9: Led::new(pe12.into_push_pull_output(moder, otyper).downgrade()),
+            ld10: Led::new(pe13.into_push_pull_output(moder, otyper).downgrade()),
         };
 
         for led in &mut leds {
@@ -167,8 +470,167 @@ impl Leds {
     ///     delay.delay_ms(ms_delay);
     /// }
     /// ```
-    pub fn iter_mut(&mut self) -> LedsMutIterator {
-        LedsMutIterator::new(self)
+    pub fn iter_mut(&mut self) -> LedsMutIterator<'_> {
+        LedsMutIterator::new(self, Direction::North)
+    }
+
+    /// Provides a mutable iterator that starts at the led for the given direction,
+    /// moves clockwise, and wraps around until all 8 leds have been visited.
+    ///
+    /// # Example
+    ///
+    /// Light the leds clockwise, starting from East
+    ///
+    /// ```
+    /// for led in leds.iter_mut_from(Direction::East) {
+    ///     led.on().ok();
+    /// }
+    /// ```
+    pub fn iter_mut_from(&mut self, start: Direction) -> LedsMutIterator<'_> {
+        LedsMutIterator::new(self, start)
+    }
+
+    /// Provides an iterator over shared references to the on board leds.
+    /// Starts at ld3 (N) and moves clockwise.
+    pub fn iter(&self) -> LedsIterator<'_> {
+        LedsIterator::new(self, Direction::North)
+    }
+
+    /// Provides an iterator over shared references to the on board leds
+    /// that starts at the given direction, moves clockwise, and wraps around.
+    pub fn iter_from(&self, start: Direction) -> LedsIterator<'_> {
+        LedsIterator::new(self, start)
+    }
+
+    /// Provides a mutable iterator that yields each led along with its direction.
+    /// Starts at ld3 (N) and moves clockwise.
+    ///
+    /// # Example
+    ///
+    /// Light every led in the northern half of the compass
+    ///
+    /// ```
+    /// for (direction, led) in leds.directions_mut() {
+    ///     match direction {
+    ///         Direction::NorthWest | Direction::North | Direction::NorthEast => led.on().ok(),
+    ///         _ => led.off().ok(),
+    ///     };
+    /// }
+    /// ```
+    pub fn directions_mut(&mut self) -> LedsDirectionsMutIterator<'_> {
+        Direction::iter().copied().zip(self.iter_mut())
+    }
+
+    /// Returns the set of leds that are currently lit.
+    ///
+    /// The state is read from the GPIOE output data register in a single access,
+    /// so it reflects what the pins are driving, regardless of how they were switched.
+    pub fn state(&self) -> LedMask {
+        // Safety: Reading ODR has no side effects,
+        // and we only look at PE8-PE15, which are owned by `Leds`.
+        let odr = unsafe { (*pac::GPIOE::ptr()).odr.read().bits() };
+        // PE8 is NorthWest and PE9 through PE15 are North through West,
+        // so the pins are the direction mask rotated one place.
+        LedMask(((odr >> 8) as u8).rotate_right(1))
+    }
+
+    /// Lights exactly the leds in `mask` and turns all others off.
+    ///
+    /// All 8 leds are updated in a single write to the GPIOE bit set/reset register,
+    /// so there is no visible intermediate state.
+    pub fn set_state(&mut self, mask: LedMask) {
+        let set = (mask.0.rotate_left(1) as u32) << 8;
+        let reset = (!set & 0xFF00) << 16;
+        // Safety: BSRR writes are atomic and only touch the pins whose bits are set,
+        // which are all PE8-PE15, owned by `Leds`.
+        unsafe { (*pac::GPIOE::ptr()).bsrr.write(|w| w.bits(set | reset)) };
+    }
+
+    /// Lights every led in `mask`, leaving the others untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// leds.turn_on(Color::Green.into());
+    /// ```
+    pub fn turn_on(&mut self, mask: LedMask) {
+        self.set_state(self.state() | mask)
+    }
+
+    /// Turns off every led in `mask`, leaving the others untouched.
+    pub fn turn_off(&mut self, mask: LedMask) {
+        self.set_state(self.state() & !mask)
+    }
+
+    /// Mutably borrow both LEDs of the given color,
+    /// in clockwise order starting from North (see [`Color::directions()`]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// for led in &mut leds.for_color(Color::Blue) {
+    ///     led.on().ok();
+    /// }
+    /// ```
+    pub fn for_color(&mut self, color: Color) -> [&mut Led; 2] {
+        match color {
+            Color::Red => [&mut self.ld3, &mut self.ld10],
+            Color::Orange => [&mut self.ld5, &mut self.ld8],
+            Color::Green => [&mut self.ld7, &mut self.ld6],
+            Color::Blue => [&mut self.ld9, &mut self.ld4],
+        }
+    }
+
+    /// Captures the current led state so it can be put back later with [`Self::restore()`].
+    pub fn snapshot(&self) -> LedMask {
+        self.state()
+    }
+
+    /// Restores a state previously captured with [`Self::snapshot()`].
+    pub fn restore(&mut self, snapshot: LedMask) {
+        self.set_state(snapshot)
+    }
+
+    /// Takes a snapshot of the leds and returns a guard that gives full access to them.
+    /// The snapshot is restored when the guard is dropped,
+    /// which makes it easy to show a transient pattern on top of a steady display.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// {
+    ///     let mut alert = leds.overlay();
+    ///     alert.set_state(LedMask::ALL);
+    ///     delay.delay_ms(500u16);
+    /// } // previous state is restored here
+    /// ```
+    pub fn overlay(&mut self) -> LedsOverlay<'_> {
+        let snapshot = self.snapshot();
+        LedsOverlay { leds: self, snapshot }
+    }
+
+    /// Consumes the `Leds` struct and releases the underlying GPIOE pins,
+    /// in pin order from PE8 to PE15.
+    ///
+    /// The pins are left configured as push pull outputs in their current state,
+    /// ready to be reconfigured, e.g. into the timer alternate functions for PWM.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let (pe8, pe9, pe10, pe11, pe12, pe13, pe14, pe15) = leds.free();
+    /// ```
+    pub fn free(self) -> LedPins {
+        (
+            into_typed_pin!(self.ld4, PE8),
+            into_typed_pin!(self.ld3, PE9),
+            into_typed_pin!(self.ld5, PE10),
+            into_typed_pin!(self.ld7, PE11),
+            into_typed_pin!(self.ld9, PE12),
+            into_typed_pin!(self.ld10, PE13),
+            into_typed_pin!(self.ld8, PE14),
+            into_typed_pin!(self.ld6, PE15),
+        )
     }
 
     /// Consumes the `Leds` struct and returns an array,
@@ -205,17 +667,96 @@ impl<'a> IntoIterator for &'a mut Leds {
     }
 }
 
+impl<'a> IntoIterator for &'a Leds {
+    type Item = &'a Led;
+    type IntoIter = LedsIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Borrow a LED by the given direction (as noted on the board)
+///
+/// # Example
+///
+/// ```
+/// leds[Direction::South].on().ok();
+/// ```
+impl Index<Direction> for Leds {
+    type Output = Led;
+
+    fn index(&self, direction: Direction) -> &Led {
+        match direction {
+            Direction::North => &self.ld3,
+            Direction::NorthEast => &self.ld5,
+            Direction::East => &self.ld7,
+            Direction::SouthEast => &self.ld9,
+            Direction::South => &self.ld10,
+            Direction::SouthWest => &self.ld8,
+            Direction::West => &self.ld6,
+            Direction::NorthWest => &self.ld4,
+        }
+    }
+}
+
+impl IndexMut<Direction> for Leds {
+    fn index_mut(&mut self, direction: Direction) -> &mut Led {
+        self.for_direction(direction)
+    }
+}
+
+/// Scoped access to the leds that restores their previous state when dropped.
+/// See [`Leds::overlay()`].
+pub struct LedsOverlay<'a> {
+    leds: &'a mut Leds,
+    snapshot: LedMask,
+}
+
+impl<'a> LedsOverlay<'a> {
+    /// The state that will be restored when the overlay is dropped
+    pub fn snapshot(&self) -> LedMask {
+        self.snapshot
+    }
+}
+
+impl<'a> Deref for LedsOverlay<'a> {
+    type Target = Leds;
+    fn deref(&self) -> &Leds {
+        self.leds
+    }
+}
+
+impl<'a> DerefMut for LedsOverlay<'a> {
+    fn deref_mut(&mut self) -> &mut Leds {
+        self.leds
+    }
+}
+
+impl<'a> Drop for LedsOverlay<'a> {
+    fn drop(&mut self) {
+        self.leds.restore(self.snapshot);
+    }
+}
+
 const ITERATOR_SIZE: usize = 8;
 
+/// Iterator yielding each led along with its direction, see [`Leds::directions_mut()`]
+pub type LedsDirectionsMutIterator<'a> = Zip<Copied<Iter<'static, Direction>>, LedsMutIterator<'a>>;
+
 pub struct LedsMutIterator<'a> {
     index: usize,
     index_back: usize,
-    leds: &'a mut Leds
+    start: usize,
+    // A raw pointer rather than `&'a mut Leds`, as reborrowing the whole struct
+    // would invalidate the references to single leds that were already handed out
+    leds: *mut Leds,
+    _leds: PhantomData<&'a mut Leds>,
 }
 
 impl<'a> LedsMutIterator<'a> {
-    fn new(leds: &'a mut Leds) -> Self {
-        LedsMutIterator { index: 0, index_back: ITERATOR_SIZE, leds }
+    fn new(leds: &'a mut Leds, start: Direction) -> Self {
+        LedsMutIterator { index: 0, index_back: ITERATOR_SIZE, start: start.index(), leds, _leds: PhantomData }
     }
 
     fn len(&self) -> usize {
@@ -226,8 +767,29 @@ impl<'a> LedsMutIterator<'a> {
         let length = self.len();
         (length, Some(length))
     }
+
+    /// Safety: Must be called at most once for each position,
+    /// otherwise we would hand out aliased mutable references.
+    unsafe fn led_at(&mut self, position: usize) -> &'a mut Led {
+        // Only the field for the led is projected, so the other leds stay borrowed by whoever holds them
+        let leds = self.leds;
+        let led = match Direction::North.rotate_cw(self.start + position) {
+            Direction::North => addr_of_mut!((*leds).ld3),
+            Direction::NorthEast => addr_of_mut!((*leds).ld5),
+            Direction::East => addr_of_mut!((*leds).ld7),
+            Direction::SouthEast => addr_of_mut!((*leds).ld9),
+            Direction::South => addr_of_mut!((*leds).ld10),
+            Direction::SouthWest => addr_of_mut!((*leds).ld8),
+            Direction::West => addr_of_mut!((*leds).ld6),
+            Direction::NorthWest => addr_of_mut!((*leds).ld4),
+        };
+        &mut *led
+    }
 }
 
+// Safety: The iterator only stands in for the `&'a mut Leds` it was created from
+unsafe impl<'a> Send for LedsMutIterator<'a> where &'a mut Leds: Send {}
+
 impl<'a> Iterator for LedsMutIterator<'a> {
     type Item = &'a mut Led;
     fn next(&mut self) -> Option<Self::Item> {
@@ -235,26 +797,16 @@ impl<'a> Iterator for LedsMutIterator<'a> {
             None
         } else {
             let current = unsafe {
-                //Safety: Each branch is only executed once,
+                //Safety: Each position is only visited once,
                 // and only if there are elements left to be returned, 
                 // so we can not possibly alias a mutable reference.
                 // This depends on DoubleEndedIterator and ExactSizedIterator being implemented correctly.
                 // If len() does not return the correct number of remaining elements, 
                 // this becomes unsound.
-                    match self.index {
-                        0 => Some(&mut *(&mut self.leds.ld3 as *mut _)),  //N
-                        1 => Some(&mut *(&mut self.leds.ld5 as *mut _)),  //NE
-                        2 => Some(&mut *(&mut self.leds.ld7 as *mut _)),  //E
-                        3 => Some(&mut *(&mut self.leds.ld9 as *mut _)),  //SE
-                        4 => Some(&mut *(&mut self.leds.ld10 as *mut _)), //S
-                        5 => Some(&mut *(&mut self.leds.ld8 as *mut _)),  //SW
-                        6 => Some(&mut *(&mut self.leds.ld6 as *mut _)),  //W
-                        7 => Some(&mut *(&mut self.leds.ld4 as *mut _)),  //NW
-                        _ => None
-                }
+                self.led_at(self.index)
             };
             self.index += 1;
-            current
+            Some(current)
         }
     }
 
@@ -269,30 +821,19 @@ impl<'a> DoubleEndedIterator for LedsMutIterator<'a> {
         if self.len() == 0 {
             None
         } else {
+            // index_back points one past the last element left to return,
+            // so step back before using it
+            self.index_back -= 1;
             let current = unsafe {
-                //Safety: Each branch is only executed once,
+                //Safety: Each position is only visited once,
                 // and only if there are elements left to be returned, 
                 // so we can not possibly alias a mutable reference.
                 // This depends on Iterator and ExactSizedIterator being implemented correctly.
                 // If len() does not return the correct number of remaining elements, 
                 // this becomes unsound.
-                    match self.index_back {
-                        // Because we're going backwards and index_back is a usize,
-                        // We use a one based index so we don't go negative
-                        0 => None, //done
-                        1 => Some(&mut *(&mut self.leds.ld3 as *mut _)),  //N
-                        2 => Some(&mut *(&mut self.leds.ld5 as *mut _)),  //NE
-                        3 => Some(&mut *(&mut self.leds.ld7 as *mut _)),  //E
-                        4 => Some(&mut *(&mut self.leds.ld9 as *mut _)),  //SE
-                        5 => Some(&mut *(&mut self.leds.ld10 as *mut _)), //S
-                        6 => Some(&mut *(&mut self.leds.ld8 as *mut _)),  //SW
-                        7 => Some(&mut *(&mut self.leds.ld6 as *mut _)),  //W
-                        8 => Some(&mut *(&mut self.leds.ld4 as *mut _)),  //NW
-                        _ => None //can't happen
-                }
+                self.led_at(self.index_back)
             };
-            self.index_back -= 1;
-            current
+            Some(current)
         }
     }
 }
@@ -304,4 +845,201 @@ impl<'a> ExactSizeIterator for LedsMutIterator<'a> {
 }
 
 ///Marker trait that indicates LedsMutIterator never starts returning Some after returning None
-impl<'a> FusedIterator for LedsMutIterator<'a> {}
\ No newline at end of file
+impl<'a> FusedIterator for LedsMutIterator<'a> {}
+
+pub struct LedsIterator<'a> {
+    index: usize,
+    index_back: usize,
+    start: usize,
+    leds: &'a Leds
+}
+
+impl<'a> LedsIterator<'a> {
+    fn new(leds: &'a Leds, start: Direction) -> Self {
+        LedsIterator { index: 0, index_back: ITERATOR_SIZE, start: start.index(), leds }
+    }
+
+    fn led_at(&self, position: usize) -> &'a Led {
+        let leds: &'a Leds = self.leds;
+        &leds[Direction::North.rotate_cw(self.start + position)]
+    }
+}
+
+impl<'a> Iterator for LedsIterator<'a> {
+    type Item = &'a Led;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.index_back {
+            None
+        } else {
+            let current = self.led_at(self.index);
+            self.index += 1;
+            Some(current)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let length = self.index_back - self.index;
+        (length, Some(length))
+    }
+}
+
+impl<'a> DoubleEndedIterator for LedsIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.index == self.index_back {
+            None
+        } else {
+            self.index_back -= 1;
+            Some(self.led_at(self.index_back))
+        }
+    }
+}
+
+impl<'a> ExactSizeIterator for LedsIterator<'a> {}
+
+impl<'a> FusedIterator for LedsIterator<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leds built from made up pins, for iterating without touching GPIOE
+    fn test_leds() -> Leds {
+        macro_rules! led {
+            ($pin:ident) => {
+                // Safety: Typed pins are zero sized markers and no registers are accessed by these tests
+                Led::new(
+                    unsafe { core::mem::transmute::<(), gpioe::$pin<Output<PushPull>>>(()) }
+                        .downgrade(),
+                )
+            };
+        }
+        Leds {
+            ld3: led!(PE9),
+            ld4: led!(PE8),
+            ld5: led!(PE10),
+            ld6: led!(PE15),
+            ld7: led!(PE11),
+            ld8: led!(PE14),
+            ld9: led!(PE12),
+            ld10: led!(PE13),
+        }
+    }
+
+    fn addresses(leds: &Leds) -> [*const Led; 8] {
+        let mut addresses = [core::ptr::null(); 8];
+        for (address, direction) in addresses.iter_mut().zip(Direction::iter()) {
+            *address = &leds[*direction];
+        }
+        addresses
+    }
+
+    #[test]
+    fn iter_mut_items_can_be_held_together() {
+        let mut leds = test_leds();
+        let expected = addresses(&leds);
+
+        let mut iter = leds.iter_mut_from(Direction::East);
+        let east = iter.next().unwrap();
+        let south_east = iter.next().unwrap();
+        let north_east = iter.next_back().unwrap();
+        // writes through all three after the later ones were handed out
+        core::mem::swap(east, south_east);
+        core::mem::swap(east, north_east);
+
+        assert_eq!(east as *const Led, expected[Direction::East.index()]);
+        assert_eq!(south_east as *const Led, expected[Direction::SouthEast.index()]);
+        assert_eq!(north_east as *const Led, expected[Direction::NorthEast.index()]);
+    }
+
+    #[test]
+    fn iter_mut_visits_every_led_once() {
+        let mut leds = test_leds();
+        let expected = addresses(&leds);
+
+        let mut held: [*const Led; 8] = [core::ptr::null(); 8];
+        for ((direction, led), slot) in leds.directions_mut().zip(held.iter_mut()) {
+            assert_eq!(led as *const Led, expected[direction.index()]);
+            *slot = led;
+        }
+        assert_eq!(held, expected);
+
+        let reversed = leds.iter_mut().rev().map(|led| led as *const Led);
+        assert!(reversed.eq(expected.iter().rev().copied()));
+    }
+
+    #[test]
+    fn from_degrees_centers() {
+        for direction in Direction::iter().copied() {
+            assert_eq!(Direction::from_degrees(direction.to_degrees()), direction);
+        }
+    }
+
+    #[test]
+    fn from_degrees_sector_boundaries() {
+        assert_eq!(Direction::from_degrees(22.49), Direction::North);
+        assert_eq!(Direction::from_degrees(22.5), Direction::NorthEast);
+        assert_eq!(Direction::from_degrees(67.49), Direction::NorthEast);
+        assert_eq!(Direction::from_degrees(67.5), Direction::East);
+        assert_eq!(Direction::from_degrees(337.49), Direction::NorthWest);
+        assert_eq!(Direction::from_degrees(337.5), Direction::North);
+        assert_eq!(Direction::from_degrees(359.99), Direction::North);
+    }
+
+    #[test]
+    fn from_degrees_wraps() {
+        assert_eq!(Direction::from_degrees(360.0), Direction::North);
+        assert_eq!(Direction::from_degrees(450.0), Direction::East);
+        assert_eq!(Direction::from_degrees(-22.5), Direction::North);
+        assert_eq!(Direction::from_degrees(-22.51), Direction::NorthWest);
+        assert_eq!(Direction::from_degrees(-90.0), Direction::West);
+        assert_eq!(Direction::from_degrees(-720.0), Direction::North);
+    }
+
+    #[test]
+    fn from_degrees_without_bearing() {
+        assert_eq!(Direction::from_degrees(f32::NAN), Direction::North);
+        assert_eq!(Direction::from_degrees(f32::INFINITY), Direction::North);
+        assert_eq!(Direction::from_degrees(f32::NEG_INFINITY), Direction::North);
+    }
+
+    #[test]
+    fn from_vector_axes_and_diagonals() {
+        assert_eq!(Direction::from_vector(0.0, 1.0), Some(Direction::North));
+        assert_eq!(Direction::from_vector(1.0, 1.0), Some(Direction::NorthEast));
+        assert_eq!(Direction::from_vector(1.0, 0.0), Some(Direction::East));
+        assert_eq!(Direction::from_vector(1.0, -1.0), Some(Direction::SouthEast));
+        assert_eq!(Direction::from_vector(0.0, -1.0), Some(Direction::South));
+        assert_eq!(Direction::from_vector(-1.0, -1.0), Some(Direction::SouthWest));
+        assert_eq!(Direction::from_vector(-1.0, 0.0), Some(Direction::West));
+        assert_eq!(Direction::from_vector(-1.0, 1.0), Some(Direction::NorthWest));
+    }
+
+    #[test]
+    fn from_vector_sector_boundaries() {
+        // exactly on the 22.5° boundary goes to the cardinal direction
+        assert_eq!(Direction::from_vector(TAN_HALF_SECTOR, 1.0), Some(Direction::North));
+        assert_eq!(Direction::from_vector(0.42, 1.0), Some(Direction::NorthEast));
+        assert_eq!(Direction::from_vector(1.0, TAN_HALF_SECTOR), Some(Direction::East));
+        assert_eq!(Direction::from_vector(1.0, 0.42), Some(Direction::NorthEast));
+        assert_eq!(Direction::from_vector(-0.42, -1.0), Some(Direction::SouthWest));
+        assert_eq!(Direction::from_vector(-0.41, -1.0), Some(Direction::South));
+    }
+
+    #[test]
+    fn from_vector_agrees_with_from_degrees() {
+        // x = sin, y = cos for a bearing clockwise from North
+        let samples = [(0.259, 0.966), (0.5, 0.866), (0.966, -0.259), (-0.707, -0.707), (-0.866, 0.5)];
+        let bearings = [15.0, 30.0, 105.0, 225.0, 300.0];
+        for (&(x, y), &degrees) in samples.iter().zip(bearings.iter()) {
+            assert_eq!(Direction::from_vector(x, y), Some(Direction::from_degrees(degrees)));
+        }
+    }
+
+    #[test]
+    fn from_vector_without_direction() {
+        assert_eq!(Direction::from_vector(0.0, 0.0), None);
+        assert_eq!(Direction::from_vector(-0.0, 0.0), None);
+        assert_eq!(Direction::from_vector(f32::NAN, 1.0), None);
+        assert_eq!(Direction::from_vector(1.0, f32::NAN), None);
+    }
+}
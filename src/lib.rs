@@ -5,9 +5,26 @@ pub use lsm303dlhc;
 pub use stm32f3xx_hal;
 pub use switch_hal;
 
+pub mod asynch;
+pub mod bootloader;
 pub mod button;
+pub mod clocks;
 pub mod compass;
+pub mod device_info;
+pub mod executor;
+pub mod exti;
+pub mod gestures;
 pub mod leds;
+pub mod power;
+pub mod queue;
+pub mod reset;
+#[cfg(feature = "rtic")]
+pub mod rtic;
+pub mod time;
+pub mod timer;
+#[cfg(feature = "usb")]
+pub mod usb;
+pub mod watchdog;
 
 /// Signals the process to go into low power mode until an interrupt occurs
 pub fn wait_for_interrupt() {
@@ -0,0 +1,341 @@
+//! Low power modes, with the user button as a wake-up source
+//!
+//! The STM32F303 has three low power modes:
+//!
+//! - **Sleep**: the core stops, peripherals and clocks keep running. Any interrupt wakes it.
+//!   This is what [`crate::wait_for_interrupt()`] does.
+//! - **Stop**: all clocks stop, RAM and registers are kept. Only EXTI lines wake it,
+//!   such as the user button interrupt from [`crate::button::interrupt`].
+//!   The MCU wakes up running from the 8 MHz HSI, so [`Power::stop()`] restores the previous clock configuration.
+//! - **Standby**: everything is powered down except the backup domain and RAM is lost.
+//!   The user button is connected to the WKUP1 pin (PA0), so a press resets the board,
+//!   which can be detected with [`Power::woke_from_standby()`].
+//!
+//! Each mode is described by a [`SleepMode`] and entered with [`Power::enter()`],
+//! waking on either an interrupt (`WFI`) or an event (`WFE`), see [`WakeOn`].
+//! Besides EXTI lines, the RTC and timers can be enabled as wake-up sources with [`Power::enable_wake_source()`],
+//! and [`SleepMeter`] keeps track of how long the board spends asleep.
+//!
+//! # Example
+//!
+//! ```
+//! let mut power = Power::new(device_periphs.PWR, &mut rcc.apb1);
+//! let button_int = button::interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
+//! loop {
+//!     // wakes up when the button is pressed
+//!     power.stop(&mut core_periphs.SCB);
+//! }
+//! ```
+use crate::clocks;
+use crate::exti::modify_exti;
+use cortex_m::asm;
+use cortex_m::peripheral::{NVIC, SCB};
+use stm32f3xx_hal::pac::{self, Interrupt, PWR};
+use stm32f3xx_hal::rcc::APB1;
+
+/// Voltage regulator setting while in Stop mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Regulator {
+    /// Regulator stays in normal mode, for the fastest wake-up
+    On,
+    /// Regulator switches to low power mode, for the lowest consumption at the cost of a longer wake-up
+    LowPower,
+}
+
+/// Low power modes, from the lightest to the deepest
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SleepMode {
+    /// Core stopped, everything else running
+    Sleep,
+    /// All clocks stopped, RAM and registers retained
+    Stop(Regulator),
+    /// Powered down, only the backup domain is retained and waking up resets the board
+    Standby,
+}
+
+/// Instruction used to enter a low power mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WakeOn {
+    /// `WFI`: wake up on an enabled interrupt, which is then serviced
+    Interrupt,
+    /// `WFE`: wake up on an event, such as an EXTI line in event mode
+    /// (see [`crate::exti::ExtiLine::enable_event()`]) or `SEV`, without servicing an interrupt
+    Event,
+}
+
+/// Wake-up sources that aren't GPIO pins.
+/// Pins are enabled through [`crate::exti::ExtiManager`] or [`crate::button::interrupt`].
+#[derive(Clone, Copy, Debug)]
+pub enum WakeSource {
+    /// RTC alarm, on EXTI line 17
+    RtcAlarm,
+    /// RTC periodic wake-up timer, on EXTI line 20
+    RtcWakeup,
+    /// Any peripheral interrupt, such as a timer update.
+    /// Peripheral clocks are stopped in Stop and Standby, so these only wake from Sleep.
+    Interrupt(Interrupt),
+}
+
+impl WakeSource {
+    /// True if the source is able to wake the board from `mode`
+    pub fn can_wake(self, mode: SleepMode) -> bool {
+        match self {
+            WakeSource::RtcAlarm | WakeSource::RtcWakeup => true,
+            WakeSource::Interrupt(_) => mode == SleepMode::Sleep,
+        }
+    }
+
+    /// EXTI line, interrupt, and whether the line must be configured
+    fn parts(self) -> (Option<u32>, Interrupt) {
+        match self {
+            WakeSource::RtcAlarm => (Some(1 << 17), Interrupt::RTCALARM),
+            WakeSource::RtcWakeup => (Some(1 << 20), Interrupt::RTC_WKUP),
+            WakeSource::Interrupt(interrupt) => (None, interrupt),
+        }
+    }
+}
+
+/// Owns the PWR peripheral and puts the MCU into its low power modes
+pub struct Power {
+    pwr: PWR,
+}
+
+impl Power {
+    /// Takes ownership of the PWR peripheral and enables its clock
+    pub fn new(pwr: PWR, apb1: &mut APB1) -> Self {
+        clocks::modify_apb1(apb1, |rcc| rcc.apb1enr.modify(|_, w| w.pwren().set_bit()));
+        Power { pwr }
+    }
+
+    /// Sets up the core and PWR so that the next `WFI`/`WFE`, or returning from an interrupt
+    /// while sleep-on-exit is enabled, enters `mode`.
+    pub fn configure(&mut self, scb: &mut SCB, mode: SleepMode) {
+        match mode {
+            SleepMode::Sleep => scb.clear_sleepdeep(),
+            SleepMode::Stop(regulator) => {
+                let low_power = regulator == Regulator::LowPower;
+                self.pwr.cr.modify(|_, w| w.pdds().clear_bit().lpds().bit(low_power));
+                scb.set_sleepdeep();
+            }
+            SleepMode::Standby => {
+                // A stale wake-up flag would wake us right away
+                self.pwr.cr.modify(|_, w| w.cwuf().set_bit());
+                self.pwr.cr.modify(|_, w| w.pdds().set_bit());
+                scb.set_sleepdeep();
+            }
+        }
+    }
+
+    /// Enters `mode` until woken up as described by `wake_on`.
+    ///
+    /// Any pending EXTI interrupt must have been cleared beforehand, or the MCU wakes up immediately.
+    /// When waking up from Stop mode, HSE, PLL and the system clock source are restored
+    /// to how they were before stopping, so the frozen `Clocks` remain valid.
+    /// Waking up from Standby resets the board, so this never returns for [`SleepMode::Standby`].
+    pub fn enter(&mut self, scb: &mut SCB, mode: SleepMode, wake_on: WakeOn) {
+        let clocks = ClockState::save();
+        self.configure(scb, mode);
+
+        asm::dsb();
+        match wake_on {
+            WakeOn::Interrupt => asm::wfi(),
+            WakeOn::Event => asm::wfe(),
+        }
+
+        if mode == SleepMode::Standby {
+            // woken by something that can't end Standby, e.g. a debugger
+            loop {
+                asm::wfi();
+            }
+        }
+
+        scb.clear_sleepdeep();
+        if mode != SleepMode::Sleep {
+            clocks.restore();
+        }
+    }
+
+    /// Enters Sleep mode until the next interrupt
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        self.enter(scb, SleepMode::Sleep, WakeOn::Interrupt)
+    }
+
+    /// Enters Stop mode with the voltage regulator in low power mode, until an EXTI interrupt arrives.
+    /// See [`Self::enter()`].
+    pub fn stop(&mut self, scb: &mut SCB) {
+        self.enter(scb, SleepMode::Stop(Regulator::LowPower), WakeOn::Interrupt)
+    }
+
+    /// Enters Standby mode. Only a user button press (with [`Self::enable_button_wakeup()`]),
+    /// a reset, or an RTC/IWDG event will wake the board, and waking up resets it.
+    pub fn standby(&mut self, scb: &mut SCB) -> ! {
+        self.enter(scb, SleepMode::Standby, WakeOn::Interrupt);
+        unreachable!()
+    }
+
+    /// Enters the mode last set with [`Self::configure()`] whenever the core returns from an interrupt handler
+    /// to thread mode, so an interrupt driven application never has to run its main loop.
+    pub fn set_sleep_on_exit(&mut self, scb: &mut SCB, enabled: bool) {
+        if enabled {
+            scb.set_sleeponexit();
+        } else {
+            scb.clear_sleeponexit();
+        }
+    }
+
+    /// Enables a non GPIO wake-up source.
+    /// The RTC itself must still be configured to raise the alarm or wake-up event.
+    ///
+    /// # Safety
+    ///
+    /// This unmasks the source's interrupt, which `Power` doesn't own,
+    /// so it can break mask-based critical sections (see [`NVIC::unmask()`]).
+    pub unsafe fn enable_wake_source(&mut self, source: WakeSource) {
+        let (line, interrupt) = source.parts();
+        if let Some(mask) = line {
+            modify_exti(|exti| {
+                exti.rtsr1.modify(|r, w| w.bits(r.bits() | mask));
+                exti.imr1.modify(|r, w| w.bits(r.bits() | mask));
+            });
+        }
+        NVIC::unmask(interrupt);
+    }
+
+    /// Disables a wake-up source enabled with [`Self::enable_wake_source()`]
+    pub fn disable_wake_source(&mut self, source: WakeSource) {
+        let (line, interrupt) = source.parts();
+        NVIC::mask(interrupt);
+        if let Some(mask) = line {
+            modify_exti(|exti| {
+                exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+                exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+            });
+        }
+    }
+
+    /// Lets a rising edge on the user button (WKUP1 on PA0) wake the board from Standby.
+    ///
+    /// While enabled, PA0 is forced into input pull down mode by hardware.
+    pub fn enable_button_wakeup(&mut self) {
+        self.pwr.csr.modify(|_, w| w.ewup1().set_bit());
+    }
+
+    /// Returns PA0 to normal GPIO operation
+    pub fn disable_button_wakeup(&mut self) {
+        self.pwr.csr.modify(|_, w| w.ewup1().clear_bit());
+    }
+
+    /// True if the last reset was caused by waking up from Standby
+    pub fn woke_from_standby(&self) -> bool {
+        self.pwr.csr.read().sbf().bit_is_set()
+    }
+
+    /// Clears the standby and wake-up flags, so the next reset is reported correctly
+    pub fn clear_standby_flag(&mut self) {
+        self.pwr.cr.modify(|_, w| w.csbf().set_bit().cwuf().set_bit());
+    }
+
+    /// Releases the PWR peripheral
+    pub fn free(self) -> PWR {
+        self.pwr
+    }
+}
+
+/// Keeps track of time spent asleep, using a clock that keeps running in the chosen mode,
+/// such as the RTC for Stop mode or a timer for Sleep mode.
+///
+/// The clock is any function returning a free running tick count,
+/// and all totals are in the same ticks. Wrapping of the count is handled.
+///
+/// # Example
+///
+/// ```
+/// let mut meter = SleepMeter::new(|| capture.now_us());
+/// loop {
+///     meter.enter(&mut power, &mut scb, SleepMode::Sleep, WakeOn::Interrupt);
+///     // ... handle the interrupt ...
+///     iprintln!(stim, "asleep {}% of the time", meter.asleep_percent());
+/// }
+/// ```
+pub struct SleepMeter<F> {
+    clock: F,
+    started: u32,
+    asleep: u64,
+}
+
+impl<F: FnMut() -> u32> SleepMeter<F> {
+    /// Starts measuring from now
+    pub fn new(mut clock: F) -> Self {
+        let started = clock();
+        SleepMeter { clock, started, asleep: 0 }
+    }
+
+    /// Enters `mode` through [`Power::enter()`] and adds the time until wake-up to the total.
+    /// Returns the ticks spent asleep this time.
+    pub fn enter(&mut self, power: &mut Power, scb: &mut SCB, mode: SleepMode, wake_on: WakeOn) -> u32 {
+        let before = (self.clock)();
+        power.enter(scb, mode, wake_on);
+        let slept = (self.clock)().wrapping_sub(before);
+        self.asleep += u64::from(slept);
+        slept
+    }
+
+    /// Total ticks spent asleep since the meter was created or reset
+    pub fn asleep(&self) -> u64 {
+        self.asleep
+    }
+
+    /// Ticks elapsed since the meter was created or reset.
+    /// Only correct while this is less than one full wrap of the clock.
+    pub fn elapsed(&mut self) -> u32 {
+        (self.clock)().wrapping_sub(self.started)
+    }
+
+    /// Share of the elapsed time spent asleep, from 0 to 100
+    pub fn asleep_percent(&mut self) -> u8 {
+        let elapsed = u64::from(self.elapsed());
+        (self.asleep.min(elapsed) * 100).checked_div(elapsed).unwrap_or(0) as u8
+    }
+
+    /// Starts measuring again from now
+    pub fn reset(&mut self) {
+        self.started = (self.clock)();
+        self.asleep = 0;
+    }
+}
+
+/// The parts of the clock tree that hardware resets when entering Stop mode
+struct ClockState {
+    hse_on: bool,
+    pll_on: bool,
+    system_clock_source: u8,
+}
+
+impl ClockState {
+    fn save() -> Self {
+        // Safety: Read only access to RCC
+        let rcc = unsafe { &(*pac::RCC::ptr()) };
+        let cr = rcc.cr.read();
+        ClockState {
+            hse_on: cr.hseon().bit_is_set(),
+            pll_on: cr.pllon().bit_is_set(),
+            system_clock_source: rcc.cfgr.read().sws().bits(),
+        }
+    }
+
+    fn restore(&self) {
+        // Safety: We only turn back on what was running before Stop mode turned it off.
+        // HSEBYP and the PLL configuration survive Stop mode, so they don't need restoring.
+        let rcc = unsafe { &(*pac::RCC::ptr()) };
+        if self.hse_on {
+            rcc.cr.modify(|_, w| w.hseon().set_bit());
+            while rcc.cr.read().hserdy().bit_is_clear() {}
+        }
+        if self.pll_on {
+            rcc.cr.modify(|_, w| w.pllon().set_bit());
+            while rcc.cr.read().pllrdy().bit_is_clear() {}
+        }
+        rcc.cfgr.modify(|_, w| unsafe { w.sw().bits(self.system_clock_source) });
+        while rcc.cfgr.read().sws().bits() != self.system_clock_source {}
+    }
+}
@@ -0,0 +1,292 @@
+//! Lock-free queue for passing board events from interrupt handlers to the main loop
+//!
+//! [`EventQueue`] is a fixed capacity single producer, single consumer ring buffer
+//! that needs neither `alloc` nor critical sections, so it can live in a `static`.
+//! It is split once into a [`Producer`] for the interrupt side and a [`Consumer`] for the main loop.
+//!
+//! # Example
+//!
+//! ```
+//! static QUEUE: EventQueue<16> = EventQueue::new();
+//! static PRODUCER: Mutex<RefCell<Option<Producer<'static, 16>>>> = Mutex::new(RefCell::new(None));
+//!
+//! #[interrupt]
+//! fn EXTI0() {
+//!     free(|cs| {
+//!         if let Some(producer) = PRODUCER.borrow(cs).borrow_mut().as_mut() {
+//!             producer.push(BoardEvent::ButtonPressed).ok();
+//!         }
+//!     });
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let (producer, mut consumer) = QUEUE.split().unwrap();
+//!     free(|cs| PRODUCER.borrow(cs).replace(Some(producer)));
+//!     loop {
+//!         while let Some(event) = consumer.pop() {
+//!             // handle event
+//!         }
+//!         wait_for_interrupt();
+//!     }
+//! }
+//! ```
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Events raised by the board's peripherals
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BoardEvent {
+    /// Rising edge on the user button (PA0)
+    ButtonPressed,
+    /// Falling edge on the user button (PA0)
+    ButtonReleased,
+    /// L3GD20 gyro INT1 (PE0)
+    GyroInterrupt,
+    /// L3GD20 gyro data ready (PE1)
+    GyroDataReady,
+    /// LSM303DLHC magnetometer data ready (PE2)
+    MagDataReady,
+    /// LSM303DLHC accelerometer INT1 (PE4)
+    AccelInterrupt1,
+    /// LSM303DLHC accelerometer INT2 (PE5)
+    AccelInterrupt2,
+    /// A timer tick, carrying a tick count or timestamp
+    Tick(u32),
+    /// Application defined event
+    User(u32),
+}
+
+/// Fixed capacity queue of [`BoardEvent`]s, holding up to `N` events.
+///
+/// The read and write positions run from `0` to `2 * N` so that a full queue
+/// can be told apart from an empty one without giving up a slot.
+pub struct EventQueue<const N: usize> {
+    buffer: UnsafeCell<[MaybeUninit<BoardEvent>; N]>,
+    /// Next position to read, only written by the consumer
+    head: AtomicUsize,
+    /// Next position to write, only written by the producer
+    tail: AtomicUsize,
+    split: AtomicBool,
+}
+
+// Safety: The buffer is only accessed through the single `Producer` and `Consumer`,
+// which never touch the same slot at the same time.
+unsafe impl<const N: usize> Sync for EventQueue<N> {}
+
+impl<const N: usize> EventQueue<N> {
+    /// Fails to compile for `N == 0`, which would divide by zero when wrapping the positions.
+    /// Indexing past the end of an array stands in for `assert!`, which isn't allowed in constants before Rust 1.57.
+    const NOT_EMPTY: () = [()][(N == 0) as usize];
+
+    /// Creates an empty queue. Being `const`, it can be used to initialize a `static`.
+    ///
+    /// A queue with a capacity of 0 is rejected at compile time.
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _: () = Self::NOT_EMPTY;
+        EventQueue {
+            buffer: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Maximum number of events the queue can hold
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Splits the queue into its producer and consumer halves.
+    /// Returns `None` if the queue has already been split,
+    /// which guarantees there is only ever one of each.
+    pub fn split(&self) -> Option<(Producer<'_, N>, Consumer<'_, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            None
+        } else {
+            Some((Producer { queue: self }, Consumer { queue: self }))
+        }
+    }
+
+    /// Pointer to the slot for `position`, without borrowing the rest of the buffer
+    fn slot(&self, position: usize) -> *mut MaybeUninit<BoardEvent> {
+        // Safety: position % N is always within the buffer
+        unsafe { (self.buffer.get() as *mut MaybeUninit<BoardEvent>).add(position % N) }
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        distance(head, tail, N)
+    }
+}
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        EventQueue::new()
+    }
+}
+
+/// Number of events between the read and write positions
+fn distance(head: usize, tail: usize, capacity: usize) -> usize {
+    (tail + 2 * capacity - head) % (2 * capacity)
+}
+
+fn advance(position: usize, capacity: usize) -> usize {
+    (position + 1) % (2 * capacity)
+}
+
+/// Write half of an [`EventQueue`], for use in interrupt handlers
+pub struct Producer<'a, const N: usize> {
+    queue: &'a EventQueue<N>,
+}
+
+impl<'a, const N: usize> Producer<'a, N> {
+    /// Adds an event to the back of the queue.
+    /// If the queue is full the event is handed back, so the caller can decide what to drop.
+    pub fn push(&mut self, event: BoardEvent) -> Result<(), BoardEvent> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let head = self.queue.head.load(Ordering::Acquire);
+        if distance(head, tail, N) == N {
+            return Err(event);
+        }
+
+        // Safety: The consumer never reads the slot at `tail` until we publish it below
+        unsafe {
+            self.queue.slot(tail).write(MaybeUninit::new(event));
+        }
+        self.queue.tail.store(advance(tail, N), Ordering::Release);
+        Ok(())
+    }
+
+    /// True if there is no room for another event
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == N
+    }
+}
+
+/// Read half of an [`EventQueue`], for use in the main loop
+pub struct Consumer<'a, const N: usize> {
+    queue: &'a EventQueue<N>,
+}
+
+impl<'a, const N: usize> Consumer<'a, N> {
+    /// Removes the oldest event from the queue
+    pub fn pop(&mut self) -> Option<BoardEvent> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let tail = self.queue.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // Safety: The producer published this slot before moving `tail` past it,
+        // and won't overwrite it until we move `head` past it below.
+        let event = unsafe { self.queue.slot(head).read().assume_init() };
+        self.queue.head.store(advance(head, N), Ordering::Release);
+        Some(event)
+    }
+
+    /// Number of events waiting to be read
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// True if there are no events waiting
+    pub fn is_empty(&self) -> bool {
+        self.queue.len() == 0
+    }
+}
+
+/// Drains the queue, oldest event first
+impl<'a, const N: usize> Iterator for Consumer<'a, N> {
+    type Item = BoardEvent;
+    fn next(&mut self) -> Option<BoardEvent> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_full() {
+        let queue: EventQueue<2> = EventQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        assert!(consumer.is_empty());
+        assert!(!producer.is_full());
+        assert_eq!(consumer.pop(), None);
+
+        producer.push(BoardEvent::ButtonPressed).unwrap();
+        assert_eq!(consumer.len(), 1);
+        assert!(!consumer.is_empty());
+        assert!(!producer.is_full());
+
+        producer.push(BoardEvent::ButtonReleased).unwrap();
+        assert_eq!(consumer.len(), 2);
+        assert!(producer.is_full());
+
+        assert_eq!(consumer.pop(), Some(BoardEvent::ButtonPressed));
+        assert_eq!(consumer.pop(), Some(BoardEvent::ButtonReleased));
+        assert_eq!(consumer.pop(), None);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn push_when_full_hands_the_event_back() {
+        let queue: EventQueue<1> = EventQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+        producer.push(BoardEvent::Tick(1)).unwrap();
+        assert_eq!(producer.push(BoardEvent::Tick(2)), Err(BoardEvent::Tick(2)));
+        assert_eq!(consumer.len(), 1);
+
+        // the queued event is untouched and there is room again once it is read
+        assert_eq!(consumer.pop(), Some(BoardEvent::Tick(1)));
+        assert_eq!(producer.push(BoardEvent::Tick(3)), Ok(()));
+        assert_eq!(consumer.pop(), Some(BoardEvent::Tick(3)));
+    }
+
+    #[test]
+    fn wraps_around_the_position_space() {
+        const N: usize = 3;
+        let queue: EventQueue<N> = EventQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        // positions run over 0..2N, so go round it a few times at every fill level
+        let mut next = 0;
+        let mut expected = 0;
+        for round in 0..4 * N {
+            let fill = round % N + 1;
+            for _ in 0..fill {
+                producer.push(BoardEvent::User(next)).unwrap();
+                next += 1;
+            }
+            assert_eq!(consumer.len(), fill);
+            assert_eq!(producer.is_full(), fill == N);
+            for _ in 0..fill {
+                assert_eq!(consumer.pop(), Some(BoardEvent::User(expected)));
+                expected += 1;
+            }
+            assert!(consumer.is_empty());
+        }
+        assert!(queue.head.load(Ordering::Relaxed) < 2 * N);
+        assert!(queue.tail.load(Ordering::Relaxed) < 2 * N);
+    }
+
+    #[test]
+    fn distance_across_the_wrap() {
+        assert_eq!(distance(0, 0, 4), 0);
+        assert_eq!(distance(6, 2, 4), 4);
+        assert_eq!(distance(7, 1, 4), 2);
+        assert_eq!(advance(7, 4), 0);
+    }
+
+    #[test]
+    fn splits_only_once() {
+        let queue: EventQueue<4> = EventQueue::new();
+        assert!(queue.split().is_some());
+        assert!(queue.split().is_none());
+    }
+}
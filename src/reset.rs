@@ -0,0 +1,158 @@
+//! What caused the last reset, from the RCC's reset flags
+//!
+//! The flags in RCC_CSR survive every reset except a power-on, and accumulate until they are cleared.
+//! [`reset_cause()`] reads and clears them the first time it is called,
+//! and returns the same cause from then on, so any part of the program can ask.
+//!
+//! Waking up from Standby resets the board too, but doesn't set any of these flags,
+//! see [`crate::power::Power::woke_from_standby()`].
+//!
+//! # Example
+//!
+//! ```
+//! let cause = reset::reset_cause();
+//! iprintln!(stim, "reset by {:?}", cause);
+//! reset::blink_reset_cause(cause, &mut leds, &mut delay);
+//! ```
+use core::sync::atomic::{AtomicU8, Ordering};
+use stm32f3xx_hal::hal::blocking::delay::DelayMs;
+use stm32f3xx_hal::pac;
+
+use crate::leds::{Color, LedMask, Leds};
+
+const LPWRRSTF: u32 = 1 << 31;
+const WWDGRSTF: u32 = 1 << 30;
+const IWDGRSTF: u32 = 1 << 29;
+const SFTRSTF: u32 = 1 << 28;
+const PORRSTF: u32 = 1 << 27;
+const PINRSTF: u32 = 1 << 26;
+const OBLRSTF: u32 = 1 << 25;
+
+/// The cause read by the first call to [`reset_cause()`], 0 until then
+static CAUSE: AtomicU8 = AtomicU8::new(0);
+
+/// Times the leds flash in [`blink_reset_cause()`]
+const BLINKS: u8 = 5;
+const BLINK_MS: u16 = 150;
+
+/// Source of the last reset
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ResetCause {
+    /// The board was powered up, or the supply dropped below the brown out threshold
+    PowerOn = 1,
+    /// The reset button or the debugger pulled NRST low
+    Pin,
+    /// The firmware requested a reset, e.g. with `SCB::sys_reset()` or a debugger after flashing
+    Software,
+    /// The independent watchdog wasn't fed in time, see [`crate::watchdog`]
+    IndependentWatchdog,
+    /// The window watchdog wasn't fed in its window
+    WindowWatchdog,
+    /// Entering Stop or Standby while the option bytes forbid it
+    LowPower,
+    /// The option bytes were reloaded
+    OptionByteLoader,
+    /// None of the flags were set, e.g. they were cleared without going through [`reset_cause()`]
+    Unknown,
+}
+
+impl ResetCause {
+    /// The cause from the raw RCC_CSR value.
+    ///
+    /// Every internal reset also pulls NRST low and sets the pin flag,
+    /// so the other flags take precedence over it.
+    pub fn from_csr_bits(csr: u32) -> Self {
+        if csr & LPWRRSTF != 0 {
+            ResetCause::LowPower
+        } else if csr & WWDGRSTF != 0 {
+            ResetCause::WindowWatchdog
+        } else if csr & IWDGRSTF != 0 {
+            ResetCause::IndependentWatchdog
+        } else if csr & SFTRSTF != 0 {
+            ResetCause::Software
+        } else if csr & PORRSTF != 0 {
+            ResetCause::PowerOn
+        } else if csr & OBLRSTF != 0 {
+            ResetCause::OptionByteLoader
+        } else if csr & PINRSTF != 0 {
+            ResetCause::Pin
+        } else {
+            ResetCause::Unknown
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        let cause = match value {
+            1 => ResetCause::PowerOn,
+            2 => ResetCause::Pin,
+            3 => ResetCause::Software,
+            4 => ResetCause::IndependentWatchdog,
+            5 => ResetCause::WindowWatchdog,
+            6 => ResetCause::LowPower,
+            7 => ResetCause::OptionByteLoader,
+            8 => ResetCause::Unknown,
+            _ => return None,
+        };
+        Some(cause)
+    }
+
+    /// True for resets that point at a fault rather than someone resetting the board:
+    /// the watchdogs and illegal low power mode entries.
+    pub fn is_abnormal(self) -> bool {
+        matches!(
+            self,
+            ResetCause::IndependentWatchdog | ResetCause::WindowWatchdog | ResetCause::LowPower
+        )
+    }
+
+    /// The leds [`blink_reset_cause()`] flashes for an abnormal reset:
+    /// red for the independent watchdog, orange for the window watchdog and blue for low power.
+    pub fn led_mask(self) -> Option<LedMask> {
+        let color = match self {
+            ResetCause::IndependentWatchdog => Color::Red,
+            ResetCause::WindowWatchdog => Color::Orange,
+            ResetCause::LowPower => Color::Blue,
+            _ => return None,
+        };
+        Some(LedMask::from(color))
+    }
+}
+
+/// The cause of the last reset.
+///
+/// The first call reads the reset flags and clears them, so the next reset is reported correctly;
+/// later calls return the same cause.
+pub fn reset_cause() -> ResetCause {
+    if let Some(cause) = ResetCause::from_u8(CAUSE.load(Ordering::Acquire)) {
+        return cause;
+    }
+
+    cortex_m::interrupt::free(|_| {
+        // another context may have read the flags before we got here
+        if let Some(cause) = ResetCause::from_u8(CAUSE.load(Ordering::Acquire)) {
+            return cause;
+        }
+        // Safety: Only the reset flags are touched, in a critical section
+        let rcc = unsafe { &(*pac::RCC::ptr()) };
+        let cause = ResetCause::from_csr_bits(rcc.csr.read().bits());
+        rcc.csr.modify(|_, w| w.rmvf().clear());
+        CAUSE.store(cause as u8, Ordering::Release);
+        cause
+    })
+}
+
+/// Flashes the leds of [`ResetCause::led_mask()`] a few times if `cause` is abnormal, and does nothing otherwise.
+/// Call it at startup to make watchdog resets visible on a deployed board. The leds are left off.
+pub fn blink_reset_cause<D: DelayMs<u16>>(cause: ResetCause, leds: &mut Leds, delay: &mut D) {
+    let mask = match cause.led_mask() {
+        Some(mask) => mask,
+        None => return,
+    };
+    for _ in 0..BLINKS {
+        leds.set_state(mask);
+        delay.delay_ms(BLINK_MS);
+        leds.set_state(LedMask::NONE);
+        delay.delay_ms(BLINK_MS);
+    }
+}
@@ -0,0 +1,381 @@
+//! Resources and a monotonic timer for [RTIC](https://rtic.rs) applications
+//!
+//! Enabled with the `rtic` feature.
+//!
+//! RTIC moves resources out of `#[init]` and into the tasks that use them, so each piece of the board
+//! comes bundled with the interrupt handle that services it:
+//!
+//! - [`Leds`] as a `#[shared]` resource, for any task to light up.
+//! - [`Button`], the user button with its EXTI0 handle, as a `#[local]` resource of the `EXTI0` task.
+//! - [`CompassDrdy`], the compass with its data ready line (PE2), as a `#[local]` resource of the `EXTI2_TSC` task.
+//! - [`MonoTimer`], a 1 MHz [`Monotonic`] on TIM2 for `spawn_after` and `spawn_at`.
+//!
+//! [`Board::new()`] sets all of them up at once from `#[init]`.
+//!
+//! # Example
+//!
+//! ```
+//! #[rtic::app(device = stm32f3_discovery::stm32f3xx_hal::pac, dispatchers = [USART1_EXTI25])]
+//! mod app {
+//!     #[monotonic(binds = TIM2, default = true)]
+//!     type Mono = MonoTimer;
+//!
+//!     #[shared]
+//!     struct Shared {
+//!         leds: Leds,
+//!     }
+//!
+//!     #[local]
+//!     struct Local {
+//!         button: Button,
+//!     }
+//!
+//!     #[init]
+//!     fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+//!         let board = Board::new(/* ... */).unwrap();
+//!         (Shared { leds: board.leds }, Local { button: board.button }, init::Monotonics(board.mono))
+//!     }
+//!
+//!     #[task(binds = EXTI0, local = [button], shared = [leds])]
+//!     fn button(mut cx: button::Context) {
+//!         if cx.local.button.on_interrupt() {
+//!             cx.shared.leds.lock(|leds| leds.ld3.toggle().ok());
+//!         }
+//!     }
+//! }
+//! ```
+use crate::button::interrupt::{self, ButtonInterrupt, TriggerMode};
+use crate::button::UserButton;
+use crate::compass::Compass;
+use crate::exti::{ExtiLine, ExtiManager, Source};
+use crate::leds::Leds;
+use crate::timer;
+use accelerometer::vector::I16x3;
+use rtic_monotonic::Monotonic;
+use stm32f3xx_hal::gpio::{gpioa, gpiob, gpioe, Input};
+use stm32f3xx_hal::i2c;
+use stm32f3xx_hal::pac::{EXTI, I2C1, SYSCFG, TIM2};
+use stm32f3xx_hal::prelude::*;
+use stm32f3xx_hal::rcc::{Clocks, APB1, APB2};
+use switch_hal::InputSwitch;
+
+/// Ticks per second of [`MonoTimer`], so one tick is one microsecond
+pub const MONO_HZ: u32 = 1_000_000;
+
+/// Point in time on the [`MonoTimer`] clock
+pub type Instant = fugit::TimerInstantU32<MONO_HZ>;
+/// Time span on the [`MonoTimer`] clock
+pub type Duration = fugit::TimerDurationU32<MONO_HZ>;
+
+/// Reasons [`Board::new()`] can fail
+#[derive(Debug)]
+pub enum Error {
+    /// The compass didn't respond on I2C1
+    Compass(i2c::Error),
+    /// TIM2 can't tick at [`MONO_HZ`] with the frozen clocks
+    Timer(timer::Error),
+}
+
+impl From<i2c::Error> for Error {
+    fn from(error: i2c::Error) -> Self {
+        Error::Compass(error)
+    }
+}
+
+impl From<timer::Error> for Error {
+    fn from(error: timer::Error) -> Self {
+        Error::Timer(error)
+    }
+}
+
+/// Everything [`Board::new()`] sets up, ready to be handed out as RTIC resources
+pub struct Board {
+    pub leds: Leds,
+    pub button: Button,
+    pub compass: CompassDrdy,
+    pub mono: MonoTimer,
+    /// Line manager for the remaining interrupt pins, EXTI0 and EXTI2 are already claimed
+    pub exti: ExtiManager,
+}
+
+impl Board {
+    /// Initializes the leds, the user button and compass with their interrupts, and the monotonic timer.
+    ///
+    /// Like the constructors of the individual parts, it only takes the pins it needs,
+    /// so the rest of GPIOA, GPIOB, and GPIOE stay available, e.g. PA11/PA12 for USB.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut rcc = cx.device.RCC.constrain();
+    /// let mut flash = cx.device.FLASH.constrain();
+    /// let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    /// let mut gpioa = cx.device.GPIOA.split(&mut rcc.ahb);
+    /// let mut gpiob = cx.device.GPIOB.split(&mut rcc.ahb);
+    /// let mut gpioe = cx.device.GPIOE.split(&mut rcc.ahb);
+    /// let board = Board::new(
+    ///     gpioa.pa0,
+    ///     &mut gpioa.moder,
+    ///     &mut gpioa.pupdr,
+    ///     gpiob.pb6,
+    ///     gpiob.pb7,
+    ///     &mut gpiob.moder,
+    ///     &mut gpiob.otyper,
+    ///     &mut gpiob.afrl,
+    ///     gpioe.pe8,
+    ///     gpioe.pe9,
+    ///     gpioe.pe10,
+    ///     gpioe.pe11,
+    ///     gpioe.pe12,
+    ///     gpioe.pe13,
+    ///     gpioe.pe14,
+    ///     gpioe.pe15,
+    ///     &mut gpioe.moder,
+    ///     &mut gpioe.otyper,
+    ///     cx.device.I2C1,
+    ///     cx.device.TIM2,
+    ///     cx.device.EXTI,
+    ///     cx.device.SYSCFG,
+    ///     clocks,
+    ///     &mut rcc.apb1,
+    ///     &mut rcc.apb2,
+    /// )
+    /// .unwrap();
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<
+        PB6Mode,
+        PB7Mode,
+        PE8Mode,
+        PE9Mode,
+        PE10Mode,
+        PE11Mode,
+        PE12Mode,
+        PE13Mode,
+        PE14Mode,
+        PE15Mode,
+    >(
+        pa0: gpioa::PA0<Input>,
+        gpioa_moder: &mut gpioa::MODER,
+        gpioa_pupdr: &mut gpioa::PUPDR,
+        pb6: gpiob::PB6<PB6Mode>,
+        pb7: gpiob::PB7<PB7Mode>,
+        gpiob_moder: &mut gpiob::MODER,
+        gpiob_otyper: &mut gpiob::OTYPER,
+        gpiob_afrl: &mut gpiob::AFRL,
+        pe8: gpioe::PE8<PE8Mode>,
+        pe9: gpioe::PE9<PE9Mode>,
+        pe10: gpioe::PE10<PE10Mode>,
+        pe11: gpioe::PE11<PE11Mode>,
+        pe12: gpioe::PE12<PE12Mode>,
+        pe13: gpioe::PE13<PE13Mode>,
+        pe14: gpioe::PE14<PE14Mode>,
+        pe15: gpioe::PE15<PE15Mode>,
+        gpioe_moder: &mut gpioe::MODER,
+        gpioe_otyper: &mut gpioe::OTYPER,
+        i2c1: I2C1,
+        tim2: TIM2,
+        exti: EXTI,
+        syscfg: SYSCFG,
+        clocks: Clocks,
+        apb1: &mut APB1,
+        apb2: &mut APB2,
+    ) -> Result<Board, Error> {
+        let leds = Leds::new(
+            pe8,
+            pe9,
+            pe10,
+            pe11,
+            pe12,
+            pe13,
+            pe14,
+            pe15,
+            gpioe_moder,
+            gpioe_otyper,
+        );
+        let compass = Compass::new(
+            pb6,
+            pb7,
+            gpiob_moder,
+            gpiob_otyper,
+            gpiob_afrl,
+            i2c1,
+            clocks,
+            apb1,
+        )?;
+        let button = UserButton::new(pa0, gpioa_moder, gpioa_pupdr);
+
+        // Both lines are free on a fresh manager
+        let mut exti = ExtiManager::new(exti, syscfg.constrain(apb2));
+        let button_int = interrupt::enable(&mut exti, TriggerMode::Rising).unwrap();
+        let drdy = exti
+            .claim(Source::COMPASS_DRDY, TriggerMode::Rising)
+            .unwrap();
+
+        Ok(Board {
+            leds,
+            button: Button::new(button, button_int),
+            compass: CompassDrdy::new(compass, drdy),
+            mono: MonoTimer::new(tim2, clocks, apb1)?,
+            exti,
+        })
+    }
+}
+
+/// The user button together with its EXTI0 interrupt, for use as a local resource of the `EXTI0` task
+pub struct Button {
+    button: UserButton,
+    interrupt: ButtonInterrupt,
+}
+
+impl Button {
+    pub fn new(button: UserButton, interrupt: ButtonInterrupt) -> Self {
+        Button { button, interrupt }
+    }
+
+    /// Services the interrupt and returns whether the button is currently pressed.
+    /// Call it from the `EXTI0` task, or the interrupt will continue to fire.
+    pub fn on_interrupt(&mut self) -> bool {
+        self.interrupt.clear();
+        self.is_pressed()
+    }
+
+    /// True while the button is held down
+    pub fn is_pressed(&self) -> bool {
+        match self.button.is_active() {
+            Ok(pressed) => pressed,
+            Err(infallible) => match infallible {},
+        }
+    }
+
+    /// The interrupt handle, e.g. to change which edges trigger it
+    pub fn interrupt(&mut self) -> &mut ButtonInterrupt {
+        &mut self.interrupt
+    }
+
+    /// Releases the button and its interrupt
+    pub fn free(self) -> (UserButton, ButtonInterrupt) {
+        (self.button, self.interrupt)
+    }
+}
+
+/// The compass together with its magnetometer data ready line (PE2, EXTI2),
+/// for use as a local resource of the `EXTI2_TSC` task
+pub struct CompassDrdy {
+    compass: Compass,
+    drdy: ExtiLine,
+}
+
+impl CompassDrdy {
+    /// # Panics
+    ///
+    /// If `drdy` isn't the line for [`Source::COMPASS_DRDY`].
+    pub fn new(compass: Compass, drdy: ExtiLine) -> Self {
+        assert_eq!(
+            drdy.source(),
+            Source::COMPASS_DRDY,
+            "CompassDrdy needs the COMPASS_DRDY line"
+        );
+        CompassDrdy { compass, drdy }
+    }
+
+    /// Services the interrupt and reads the new magnetometer sample.
+    /// Call it from the `EXTI2_TSC` task, or the interrupt will continue to fire.
+    pub fn on_interrupt(&mut self) -> Result<I16x3, i2c::Error> {
+        self.drdy.clear();
+        self.compass.mag_raw()
+    }
+
+    /// The compass, e.g. to read the accelerometer as well
+    pub fn compass(&mut self) -> &mut Compass {
+        &mut self.compass
+    }
+
+    /// Releases the compass and its data ready line
+    pub fn free(self) -> (Compass, ExtiLine) {
+        (self.compass, self.drdy)
+    }
+}
+
+/// 32 bit [`Monotonic`] timer on TIM2, ticking at 1 MHz and wrapping around every ~71 minutes.
+///
+/// TIM2 is also used by [`crate::button::capture`], so only one of them can be used at a time.
+pub struct MonoTimer {
+    tim: TIM2,
+}
+
+impl MonoTimer {
+    /// Takes over TIM2 and starts counting.
+    /// Fails if the TIM2 clock can't be divided down to [`MONO_HZ`], e.g. because it is slower than that.
+    pub fn new(tim2: TIM2, clocks: Clocks, apb1: &mut APB1) -> Result<Self, timer::Error> {
+        timer::start_free_running(&tim2, clocks, apb1, MONO_HZ)?;
+        Ok(MonoTimer { tim: tim2 })
+    }
+
+    /// Stops the timer and releases TIM2
+    pub fn free(self) -> TIM2 {
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        self.tim.dier.reset();
+        self.tim
+    }
+}
+
+impl Monotonic for MonoTimer {
+    type Instant = Instant;
+    type Duration = Duration;
+
+    fn now(&mut self) -> Instant {
+        Instant::from_ticks(self.tim.cnt.read().bits())
+    }
+
+    fn set_compare(&mut self, instant: Instant) {
+        // channel 1 is left in frozen output compare mode, so a match only raises CC1IF
+        self.tim.ccr1.write(|w| unsafe { w.bits(instant.ticks()) });
+    }
+
+    fn clear_compare_flag(&mut self) {
+        const CC1IF: u32 = 1 << 1;
+        timer::clear_flags(&self.tim, CC1IF);
+    }
+
+    fn zero() -> Instant {
+        Instant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        // restart the count from zero, then interrupt on compare matches
+        self.tim.egr.write(|w| w.ug().set_bit());
+        self.tim.sr.write(|w| w.bits(0));
+        self.tim.dier.modify(|_, w| w.cc1ie().set_bit());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instants_compare_across_the_wrap() {
+        let before_wrap = Instant::from_ticks(u32::MAX - 10);
+        let after_wrap = Instant::from_ticks(5);
+        assert!(before_wrap < after_wrap);
+        assert_eq!(after_wrap - before_wrap, Duration::from_ticks(16));
+        assert_eq!(before_wrap + Duration::micros(16), after_wrap);
+    }
+
+    #[test]
+    fn instants_compare_within_half_the_range() {
+        let now = Instant::from_ticks(0);
+        let almost_half = Instant::from_ticks(u32::MAX / 2);
+        let past_half = Instant::from_ticks(u32::MAX / 2 + 2);
+        assert!(now < almost_half);
+        // more than half the range ahead reads as in the past, ~35 minutes at 1 MHz
+        assert!(past_half < now);
+    }
+
+    #[test]
+    fn durations_are_microseconds() {
+        assert_eq!(Duration::millis(5).ticks(), 5_000);
+        assert_eq!(Duration::secs(60).ticks(), 60_000_000);
+    }
+}
@@ -0,0 +1,361 @@
+//! Monotonic millisecond timebase driven by SysTick
+//!
+//! [`Clock`] configures SysTick to interrupt every millisecond, and the `SysTick` exception
+//! counts them with [`on_systick()`]. The count is 64 bits wide, so [`Instant`]s never wrap around.
+//! Spans of time are [`core::time::Duration`]s.
+//!
+//! [`Deadline`] and [`Periodic`] are non-blocking helpers for timeouts and fixed rate work in a main loop,
+//! e.g. debouncing, LED animations or sensor logging.
+//!
+//! # Example
+//!
+//! ```
+//! #[exception]
+//! fn SysTick() {
+//!     time::on_systick();
+//! }
+//!
+//! let clock = Clock::new(core_periphs.SYST, clocks);
+//! let mut blink = Periodic::new(Duration::from_millis(500));
+//! let mut sample = Periodic::new(Duration::from_millis(100));
+//! loop {
+//!     if blink.poll() {
+//!         leds.ld3.toggle().ok();
+//!     }
+//!     if sample.poll() {
+//!         let reading = compass.accel_raw().unwrap();
+//!         iprintln!(stim, "{}ms: {:?}", time::now().as_millis(), reading);
+//!     }
+//!     wait_for_interrupt();
+//! }
+//! ```
+use core::cell::Cell;
+use core::ops::{Add, AddAssign, Sub};
+use core::time::Duration;
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::SYST;
+use stm32f3xx_hal::rcc::Clocks;
+
+/// Milliseconds since [`Clock::new()`].
+/// Only `on_systick` writes it, but a 64 bit value can't be read atomically, hence the critical section.
+static MILLIS: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+
+/// Counts a millisecond. Call it from the `SysTick` exception.
+///
+/// [`crate::asynch::on_systick()`] already calls this, so only one of them is needed.
+pub fn on_systick() {
+    interrupt::free(|cs| {
+        let millis = MILLIS.borrow(cs);
+        millis.set(millis.get() + 1);
+    });
+}
+
+/// The current time. Stays at zero until a [`Clock`] is started.
+pub fn now() -> Instant {
+    Instant { millis: interrupt::free(|cs| MILLIS.borrow(cs).get()) }
+}
+
+/// Owns SysTick while it drives the timebase
+pub struct Clock {
+    syst: SYST,
+}
+
+impl Clock {
+    /// Configures SysTick to interrupt every millisecond and starts counting from zero.
+    /// The `SysTick` exception must call [`on_systick()`].
+    pub fn new(mut syst: SYST, clocks: Clocks) -> Self {
+        interrupt::free(|cs| MILLIS.borrow(cs).set(0));
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(clocks.sysclk().0 / 1_000 - 1);
+        syst.clear_current();
+        syst.enable_interrupt();
+        syst.enable_counter();
+        Clock { syst }
+    }
+
+    /// The current time, same as [`now()`]
+    pub fn now(&self) -> Instant {
+        now()
+    }
+
+    /// Stops SysTick and releases it. The time stops advancing.
+    pub fn free(mut self) -> SYST {
+        self.syst.disable_interrupt();
+        self.syst.disable_counter();
+        self.syst
+    }
+}
+
+/// A point in time, in milliseconds since the [`Clock`] started
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant {
+    millis: u64,
+}
+
+impl Instant {
+    /// The time the clock started
+    pub const ZERO: Instant = Instant { millis: 0 };
+
+    /// The latest representable time, hundreds of millions of years away.
+    /// Adding a [`Duration`] saturates here, so a [`Deadline`] this far out never expires.
+    pub const MAX: Instant = Instant { millis: u64::MAX };
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Instant { millis }
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        self.millis
+    }
+
+    /// Time elapsed from `earlier` to `self`, or zero if `earlier` is later
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_millis(self.millis.saturating_sub(earlier.millis))
+    }
+
+    /// Time elapsed since `self`
+    pub fn elapsed(self) -> Duration {
+        now().duration_since(self)
+    }
+
+    /// `self + duration`, or `None` on overflow
+    pub fn checked_add(self, duration: Duration) -> Option<Instant> {
+        let millis = to_millis(duration)?;
+        self.millis.checked_add(millis).map(Instant::from_millis)
+    }
+
+    /// `self - duration`, or `None` if that is before the clock started
+    pub fn checked_sub(self, duration: Duration) -> Option<Instant> {
+        let millis = to_millis(duration)?;
+        self.millis.checked_sub(millis).map(Instant::from_millis)
+    }
+}
+
+/// Whole milliseconds in `duration`, rounding down
+fn to_millis(duration: Duration) -> Option<u64> {
+    let millis = duration.as_millis();
+    if millis > u128::from(u64::MAX) {
+        None
+    } else {
+        Some(millis as u64)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates at [`Instant::MAX`], see [`Instant::checked_add()`] to detect that
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).unwrap_or(Instant::MAX)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates at [`Instant::ZERO`], see [`Instant::checked_sub()`] to detect that
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).unwrap_or(Instant::ZERO)
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Same as [`Instant::duration_since()`]
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// A point in time to check against without blocking, e.g. a timeout
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// Expires `timeout` from now, or never if that is past [`Instant::MAX`]
+    pub fn new(timeout: Duration) -> Self {
+        Deadline::at(now() + timeout)
+    }
+
+    /// Expires at `instant`
+    pub fn at(instant: Instant) -> Self {
+        Deadline { at: instant }
+    }
+
+    /// When the deadline expires
+    pub fn instant(self) -> Instant {
+        self.at
+    }
+
+    /// True once the deadline has passed
+    pub fn is_expired(self) -> bool {
+        self.is_expired_at(now())
+    }
+
+    fn is_expired_at(self, now: Instant) -> bool {
+        // `now` never reaches `Instant::MAX`, so a deadline there never expires
+        now >= self.at && self.at != Instant::MAX
+    }
+
+    /// Time left until the deadline, or zero if it has passed
+    pub fn remaining(self) -> Duration {
+        self.at.duration_since(now())
+    }
+
+    /// Pushes the deadline back to `timeout` from now, e.g. to restart a timeout on activity
+    pub fn reset(&mut self, timeout: Duration) {
+        *self = Deadline::new(timeout);
+    }
+}
+
+/// Fires at a fixed rate when polled from a loop.
+///
+/// Each period is scheduled from the previous one rather than from when it was polled, so the rate doesn't drift.
+/// If the loop falls behind by more than a period, the missed periods are skipped rather than fired back to back.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Periodic {
+    period: Duration,
+    next: Instant,
+}
+
+impl Periodic {
+    /// Fires every `period`, for the first time one period from now.
+    /// A period too long for an [`Instant`] never fires.
+    pub fn new(period: Duration) -> Self {
+        Periodic::starting_at(period, now())
+    }
+
+    fn starting_at(period: Duration, now: Instant) -> Self {
+        Periodic { period, next: now + period }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns true once per elapsed period
+    pub fn poll(&mut self) -> bool {
+        self.poll_at(now())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> bool {
+        if now < self.next || self.next == Instant::MAX {
+            return false;
+        }
+
+        self.next += self.period;
+        if self.next <= now {
+            // fell behind, skip to the next period in the future
+            let period = to_millis(self.period).unwrap_or(u64::MAX).max(1);
+            let behind = now.as_millis() - self.next.as_millis();
+            let skip = (behind / period + 1).saturating_mul(period);
+            self.next = Instant::from_millis(self.next.as_millis().saturating_add(skip));
+        }
+        true
+    }
+
+    /// Time left until the next period, or zero if it is due
+    pub fn remaining(&self) -> Duration {
+        self.next.duration_since(now())
+    }
+
+    /// Starts counting the period again from now
+    pub fn reset(&mut self) {
+        self.next = now() + self.period;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    #[test]
+    fn adding_saturates_at_max() {
+        assert_eq!(ms(u64::MAX - 1) + Duration::from_millis(5), Instant::MAX);
+        assert_eq!(Instant::ZERO + Duration::from_secs(u64::MAX), Instant::MAX);
+        assert_eq!(ms(10) + Duration::from_micros(1_999), ms(11));
+
+        let mut instant = ms(u64::MAX - 1);
+        instant += Duration::from_millis(2);
+        assert_eq!(instant, Instant::MAX);
+        assert_eq!(ms(u64::MAX - 1).checked_add(Duration::from_millis(2)), None);
+    }
+
+    #[test]
+    fn subtracting_saturates_at_zero() {
+        assert_eq!(ms(5) - Duration::from_millis(10), Instant::ZERO);
+        assert_eq!(Instant::MAX - Duration::from_secs(u64::MAX), Instant::ZERO);
+        assert_eq!(ms(10) - Duration::from_millis(3), ms(7));
+        assert_eq!(ms(3) - ms(10), Duration::from_millis(0));
+        assert_eq!(ms(5).checked_sub(Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn deadline_expires_at_its_instant() {
+        let deadline = Deadline::at(ms(100));
+        assert!(!deadline.is_expired_at(ms(99)));
+        assert!(deadline.is_expired_at(ms(100)));
+        assert!(deadline.is_expired_at(ms(101)));
+    }
+
+    #[test]
+    fn deadline_at_max_never_expires() {
+        let deadline = Deadline::at(ms(u64::MAX - 10) + Duration::from_secs(1));
+        assert_eq!(deadline.instant(), Instant::MAX);
+        assert!(!deadline.is_expired_at(ms(u64::MAX - 1)));
+        assert!(!deadline.is_expired_at(Instant::MAX));
+    }
+
+    #[test]
+    fn periodic_fires_on_each_period() {
+        let mut periodic = Periodic::starting_at(Duration::from_millis(10), ms(0));
+        assert!(!periodic.poll_at(ms(9)));
+        assert!(periodic.poll_at(ms(10)));
+        assert!(!periodic.poll_at(ms(10)));
+        // late polls don't shift the schedule
+        assert!(periodic.poll_at(ms(23)));
+        assert!(!periodic.poll_at(ms(29)));
+        assert!(periodic.poll_at(ms(30)));
+    }
+
+    #[test]
+    fn periodic_skips_missed_periods() {
+        let mut periodic = Periodic::starting_at(Duration::from_millis(10), ms(0));
+        assert!(periodic.poll_at(ms(35)));
+        assert!(!periodic.poll_at(ms(39)));
+        assert!(periodic.poll_at(ms(40)));
+    }
+
+    #[test]
+    fn periodic_with_zero_period_fires_on_every_poll() {
+        let mut periodic = Periodic::starting_at(Duration::from_millis(0), ms(5));
+        assert!(periodic.poll_at(ms(5)));
+        assert!(periodic.poll_at(ms(6)));
+        assert!(periodic.poll_at(ms(100)));
+    }
+
+    #[test]
+    fn periodic_near_max_stops_instead_of_overflowing() {
+        let mut periodic = Periodic::starting_at(Duration::from_secs(u64::MAX), ms(5));
+        assert!(!periodic.poll_at(ms(u64::MAX - 1)));
+
+        let mut periodic = Periodic::starting_at(Duration::from_millis(10), ms(u64::MAX - 25));
+        assert!(periodic.poll_at(ms(u64::MAX - 15)));
+        assert!(periodic.poll_at(ms(u64::MAX - 1)));
+        assert!(!periodic.poll_at(ms(u64::MAX - 1)));
+    }
+}
@@ -0,0 +1,129 @@
+//! TIM2 as a free running 32 bit counter, shared by [`crate::button::capture`] and the RTIC monotonic
+//!
+//! Both need the same setup, so only one of them can own TIM2 at a time.
+use core::convert::TryFrom;
+use stm32f3xx_hal::pac::TIM2;
+use stm32f3xx_hal::rcc::{Clocks, APB1};
+
+use crate::clocks;
+
+/// Reasons TIM2 can't count at the requested rate
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// A tick rate of 0 Hz was requested
+    ZeroTickRate,
+    /// The timer clock is slower than the tick rate
+    ClockTooSlow { timer_hz: u32, tick_hz: u32 },
+    /// The timer clock is more than 65536 times the tick rate, which the 16 bit prescaler can't divide down
+    PrescalerOverflow { timer_hz: u32, tick_hz: u32 },
+    /// The timer clock isn't a whole multiple of the tick rate, so the timer would tick at a different rate
+    InexactRate { timer_hz: u32, tick_hz: u32 },
+}
+
+/// Clock of the timers on APB1, e.g. TIM2.
+/// They run at twice the bus clock whenever the bus is divided down.
+pub fn apb1_timer_clock(clocks: Clocks) -> u32 {
+    timer_clock(clocks.pclk1().0, clocks.ppre1())
+}
+
+fn timer_clock(pclk_hz: u32, ppre: u8) -> u32 {
+    if ppre == 1 {
+        pclk_hz
+    } else {
+        pclk_hz * 2
+    }
+}
+
+/// Prescaler register value that divides `timer_hz` down to `tick_hz`
+fn prescaler(timer_hz: u32, tick_hz: u32) -> Result<u16, Error> {
+    if tick_hz == 0 {
+        return Err(Error::ZeroTickRate);
+    }
+    if timer_hz < tick_hz {
+        return Err(Error::ClockTooSlow { timer_hz, tick_hz });
+    }
+    let divider = timer_hz / tick_hz;
+    if divider * tick_hz != timer_hz {
+        return Err(Error::InexactRate { timer_hz, tick_hz });
+    }
+    u16::try_from(divider - 1).map_err(|_| Error::PrescalerOverflow { timer_hz, tick_hz })
+}
+
+/// Enables and resets TIM2, then starts it counting up from 0 at `tick_hz`, wrapping around after `u32::MAX`.
+/// The channels and interrupts are left in their reset state for the caller to set up.
+///
+/// Nothing is touched if the rate can't be reached.
+pub(crate) fn start_free_running(tim2: &TIM2, clocks: Clocks, apb1: &mut APB1, tick_hz: u32) -> Result<(), Error> {
+    let prescaler = prescaler(apb1_timer_clock(clocks), tick_hz)?;
+
+    clocks::modify_apb1(apb1, |rcc| {
+        rcc.apb1enr.modify(|_, w| w.tim2en().enabled());
+        rcc.apb1rstr.modify(|_, w| w.tim2rst().reset());
+        rcc.apb1rstr.modify(|_, w| w.tim2rst().clear_bit());
+    });
+
+    tim2.psc.write(|w| w.psc().bits(prescaler));
+    tim2.arr.write(|w| unsafe { w.bits(u32::MAX) });
+    // load the prescaler, then throw away the update flag it raises
+    tim2.egr.write(|w| w.ug().set_bit());
+    tim2.sr.write(|w| unsafe { w.bits(0) });
+    tim2.cr1.modify(|_, w| w.cen().set_bit());
+    Ok(())
+}
+
+/// Clears the status flags set in `flags`.
+///
+/// The flags are cleared by writing 0 and left alone by writing 1, so this is a single write
+/// rather than a read-modify-write, which could clear a flag that was raised in between.
+pub(crate) fn clear_flags(tim2: &TIM2, flags: u32) {
+    tim2.sr.write(|w| unsafe { w.bits(!flags) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apb1_timers_double_a_divided_bus() {
+        assert_eq!(timer_clock(8_000_000, 1), 8_000_000);
+        assert_eq!(timer_clock(36_000_000, 2), 72_000_000);
+        assert_eq!(timer_clock(2_000_000, 16), 4_000_000);
+    }
+
+    #[test]
+    fn prescaler_divides_down_to_the_tick_rate() {
+        assert_eq!(prescaler(1_000_000, 1_000_000), Ok(0));
+        assert_eq!(prescaler(72_000_000, 1_000_000), Ok(71));
+        assert_eq!(prescaler(65_536_000, 1000), Ok(u16::MAX));
+    }
+
+    #[test]
+    fn prescaler_rejects_a_rate_it_cant_divide_exactly() {
+        assert_eq!(
+            prescaler(72_000_000, 7_000_000),
+            Err(Error::InexactRate { timer_hz: 72_000_000, tick_hz: 7_000_000 })
+        );
+        assert_eq!(
+            prescaler(65_536_999, 1000),
+            Err(Error::InexactRate { timer_hz: 65_536_999, tick_hz: 1000 })
+        );
+    }
+
+    #[test]
+    fn prescaler_rejects_a_zero_tick_rate() {
+        assert_eq!(prescaler(72_000_000, 0), Err(Error::ZeroTickRate));
+        assert_eq!(prescaler(0, 0), Err(Error::ZeroTickRate));
+    }
+
+    #[test]
+    fn prescaler_out_of_range() {
+        assert_eq!(
+            prescaler(500_000, 1_000_000),
+            Err(Error::ClockTooSlow { timer_hz: 500_000, tick_hz: 1_000_000 })
+        );
+        assert_eq!(
+            prescaler(72_000_000, 1000),
+            Err(Error::PrescalerOverflow { timer_hz: 72_000_000, tick_hz: 1000 })
+        );
+    }
+}
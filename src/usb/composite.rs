@@ -0,0 +1,221 @@
+//! Composite USB device with a CDC-ACM console and a vendor specific bulk endpoint for sensor data
+//!
+//! The console takes text commands like [`super::UsbSerial`], while sample frames stream out
+//! on their own bulk endpoint at full USB speed instead of through the console.
+//! The data interface has class `0xff` and no driver of its own, so the host reads it with libusb,
+//! e.g. `pyusb` or `rusb`, from the bulk IN endpoint of interface [`DATA_INTERFACE`].
+//!
+//! # Frame format
+//!
+//! Every frame is a single bulk transfer of at most 64 bytes, little endian:
+//!
+//! | Bytes | Field                                                                      |
+//! |-------|----------------------------------------------------------------------------|
+//! | 0-1   | sequence number, incremented for every pushed frame, including dropped ones |
+//! | 2-5   | timestamp of the first sample, in whatever unit the caller uses            |
+//! | 6     | number of samples, at most [`MAX_SAMPLES_PER_FRAME`]                       |
+//! | 7     | reserved, 0                                                                |
+//! | 8-    | the samples, each as x, y, z `i16`s                                        |
+//!
+//! # Example
+//!
+//! ```
+//! let mut usb = UsbComposite::new(&usb_bus);
+//! let mut samples = [I16x3::default(); MAX_SAMPLES_PER_FRAME];
+//! let mut count = 0;
+//! loop {
+//!     usb.poll();
+//!     if sample_timer.poll() {
+//!         samples[count] = compass.accel_raw().unwrap();
+//!         count += 1;
+//!         if count == samples.len() {
+//!             usb.push_frame(time::now().as_millis() as u32, &samples).ok();
+//!             count = 0;
+//!         }
+//!     }
+//! }
+//! ```
+use accelerometer::vector::I16x3;
+use core::fmt;
+use usb_device::class_prelude::*;
+use usb_device::prelude::*;
+use usb_device::UsbError;
+use usbd_serial::SerialPort;
+
+use super::{UsbBusType, UsbIds};
+
+/// pid.codes test product ID for [`UsbComposite`], see [`UsbIds::test()`]
+pub const COMPOSITE_PID: u16 = 0x0004;
+
+/// Interface number of the data interface, after the console's two interfaces
+pub const DATA_INTERFACE: u8 = 2;
+
+/// Largest packet on a full speed bulk endpoint
+const MAX_PACKET_SIZE: usize = 64;
+/// Largest encoded frame
+pub const MAX_FRAME_LEN: usize = MAX_PACKET_SIZE;
+const FRAME_HEADER_LEN: usize = 8;
+const SAMPLE_LEN: usize = 6;
+
+/// Most samples that fit in one frame
+pub const MAX_SAMPLES_PER_FRAME: usize = (MAX_PACKET_SIZE - FRAME_HEADER_LEN) / SAMPLE_LEN;
+
+/// Interface class code for vendor specific interfaces
+const USB_CLASS_VENDOR: u8 = 0xff;
+
+/// A vendor specific interface with a single bulk IN endpoint
+pub struct DataClass<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    endpoint: EndpointIn<'a, B>,
+}
+
+impl<'a, B: UsbBus> DataClass<'a, B> {
+    pub fn new(bus: &'a UsbBusAllocator<B>) -> Self {
+        DataClass { interface: bus.interface(), endpoint: bus.bulk(MAX_PACKET_SIZE as u16) }
+    }
+
+    /// Queues one packet of up to 64 bytes.
+    /// Returns `Err(UsbError::WouldBlock)` while the host hasn't read the previous one yet.
+    pub fn write_packet(&mut self, data: &[u8]) -> usb_device::Result<usize> {
+        self.endpoint.write(data)
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for DataClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
+        writer.interface(self.interface, USB_CLASS_VENDOR, 0x00, 0x00)?;
+        writer.endpoint(&self.endpoint)
+    }
+}
+
+/// Encodes a frame into `buffer`, see the [module documentation](self), returning its length.
+///
+/// # Panics
+///
+/// If there are more than [`MAX_SAMPLES_PER_FRAME`] samples.
+pub fn encode_frame(sequence: u16, timestamp: u32, samples: &[I16x3], buffer: &mut [u8; MAX_FRAME_LEN]) -> usize {
+    assert!(samples.len() <= MAX_SAMPLES_PER_FRAME, "too many samples for one frame");
+
+    buffer[0..2].copy_from_slice(&sequence.to_le_bytes());
+    buffer[2..6].copy_from_slice(&timestamp.to_le_bytes());
+    buffer[6] = samples.len() as u8;
+    buffer[7] = 0;
+    for (sample, bytes) in samples.iter().zip(buffer[FRAME_HEADER_LEN..].chunks_exact_mut(SAMPLE_LEN)) {
+        bytes[0..2].copy_from_slice(&sample.x.to_le_bytes());
+        bytes[2..4].copy_from_slice(&sample.y.to_le_bytes());
+        bytes[4..6].copy_from_slice(&sample.z.to_le_bytes());
+    }
+    FRAME_HEADER_LEN + samples.len() * SAMPLE_LEN
+}
+
+/// A composite USB device on the USER USB connector, with a CDC-ACM console and a bulk data endpoint.
+///
+/// The console is written through [`Self::write()`] or `core::fmt::Write`, same as [`super::UsbSerial`].
+pub struct UsbComposite<'a> {
+    device: UsbDevice<'a, UsbBusType>,
+    console: SerialPort<'a, UsbBusType>,
+    data: DataClass<'a, UsbBusType>,
+    sequence: u16,
+}
+
+impl<'a> UsbComposite<'a> {
+    /// Builds the console and data classes and the USB device around them, with the test IDs from [`UsbIds::test()`]
+    pub fn new(bus: &'a UsbBusAllocator<UsbBusType>) -> Self {
+        UsbComposite::with_ids(bus, UsbIds::test(COMPOSITE_PID))
+    }
+
+    /// Same as [`Self::new()`], but identifies itself to the host with `ids`
+    pub fn with_ids(bus: &'a UsbBusAllocator<UsbBusType>, ids: UsbIds) -> Self {
+        let console = SerialPort::new(bus);
+        let data = DataClass::new(bus);
+        let device = ids.device(bus).composite_with_iads().build();
+        UsbComposite { device, console, data, sequence: 0 }
+    }
+
+    /// Services the USB peripheral. Must be called at least every 10 ms, or on every USB interrupt.
+    /// Returns true if there may be new console input to read.
+    pub fn poll(&mut self) -> bool {
+        self.device.poll(&mut [&mut self.console, &mut self.data])
+    }
+
+    /// True once the host has configured the device, so frames can be sent
+    pub fn is_configured(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured
+    }
+
+    /// True once the host has configured the device and opened the console
+    pub fn is_console_connected(&self) -> bool {
+        self.is_configured() && self.console.dtr()
+    }
+
+    /// Reads the console input received so far into `buffer`, returning how many bytes were read
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError> {
+        match self.console.read(buffer) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Queues as much of `data` as fits for sending on the console, returning how many bytes were queued
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        match self.console.write(data) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Writes all of `data` to the console, polling the device while waiting for the host to make room.
+    /// Nothing is sent while no host has the console open.
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), UsbError> {
+        while !data.is_empty() {
+            if !self.is_console_connected() {
+                return Ok(());
+            }
+            let written = self.write(data)?;
+            data = &data[written..];
+            if written == 0 {
+                self.poll();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a frame of up to [`MAX_SAMPLES_PER_FRAME`] samples on the data endpoint.
+    ///
+    /// Returns false if the frame was dropped, because the host isn't reading or hasn't read the previous frame yet.
+    /// The sequence number advances either way, so the host can tell how many frames it missed.
+    /// Too many samples is a `UsbError::BufferOverflow`.
+    pub fn push_frame(&mut self, timestamp: u32, samples: &[I16x3]) -> Result<bool, UsbError> {
+        if samples.len() > MAX_SAMPLES_PER_FRAME {
+            return Err(UsbError::BufferOverflow);
+        }
+
+        let mut frame = [0; MAX_FRAME_LEN];
+        let len = encode_frame(self.sequence, timestamp, samples, &mut frame);
+        self.sequence = self.sequence.wrapping_add(1);
+        if !self.is_configured() {
+            return Ok(false);
+        }
+        match self.data.write_packet(&frame[..len]) {
+            Ok(_) => Ok(true),
+            Err(UsbError::WouldBlock) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The underlying USB device, e.g. to check its state
+    pub fn device(&mut self) -> &mut UsbDevice<'a, UsbBusType> {
+        &mut self.device
+    }
+
+    /// Releases the USB device and both classes
+    pub fn free(self) -> (UsbDevice<'a, UsbBusType>, SerialPort<'a, UsbBusType>, DataClass<'a, UsbBusType>) {
+        (self.device, self.console, self.data)
+    }
+}
+
+impl<'a> fmt::Write for UsbComposite<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
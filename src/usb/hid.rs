@@ -0,0 +1,158 @@
+//! Minimal USB HID class with a single interrupt IN endpoint
+//!
+//! [`HidClass`] serves a fixed report descriptor and sends input reports.
+//! It implements the requests hosts need to enumerate boot-protocol and report-protocol devices,
+//! which is all the board's [`super::mouse`] and [`super::keyboard`] need.
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::Result;
+
+/// Interface class code for HID
+pub const USB_CLASS_HID: u8 = 0x03;
+
+/// HID interface subclass
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Subclass {
+    None = 0x00,
+    /// Supports the fixed report format BIOSes understand, see [`Protocol`]
+    Boot = 0x01,
+}
+
+/// HID interface protocol, only meaningful with [`Subclass::Boot`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Protocol {
+    None = 0x00,
+    Keyboard = 0x01,
+    Mouse = 0x02,
+}
+
+const HID_DESCRIPTOR: u8 = 0x21;
+const REPORT_DESCRIPTOR: u8 = 0x22;
+
+const GET_REPORT: u8 = 0x01;
+const GET_IDLE: u8 = 0x02;
+const GET_PROTOCOL: u8 = 0x03;
+const SET_REPORT: u8 = 0x09;
+const SET_IDLE: u8 = 0x0a;
+const SET_PROTOCOL: u8 = 0x0b;
+
+/// A HID interface describing its reports with `report_descriptor`
+pub struct HidClass<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    endpoint: EndpointIn<'a, B>,
+    report_descriptor: &'static [u8],
+    subclass: Subclass,
+    protocol: Protocol,
+    /// Idle rate set by the host, in units of 4 ms. Reports are only sent on change regardless.
+    idle: u8,
+    /// 0 while the host uses the boot protocol, 1 for the report protocol
+    active_protocol: u8,
+}
+
+impl<'a, B: UsbBus> HidClass<'a, B> {
+    /// Allocates the interface and an interrupt endpoint polled every `poll_ms` milliseconds,
+    /// for reports of up to `max_report_len` bytes.
+    pub fn new(
+        bus: &'a UsbBusAllocator<B>,
+        report_descriptor: &'static [u8],
+        max_report_len: u16,
+        poll_ms: u8,
+        subclass: Subclass,
+        protocol: Protocol,
+    ) -> Self {
+        HidClass {
+            interface: bus.interface(),
+            endpoint: bus.interrupt(max_report_len, poll_ms),
+            report_descriptor,
+            subclass,
+            protocol,
+            idle: 0,
+            active_protocol: 1,
+        }
+    }
+
+    /// Queues an input report, returning how many bytes were queued.
+    /// Returns `Err(UsbError::WouldBlock)` while the host hasn't read the previous report yet.
+    pub fn push_report(&mut self, report: &[u8]) -> Result<usize> {
+        self.endpoint.write(report)
+    }
+
+    /// True while the host has switched the interface to the boot protocol
+    pub fn is_boot_protocol(&self) -> bool {
+        self.active_protocol == 0
+    }
+
+    fn hid_descriptor(&self) -> [u8; 7] {
+        let len = self.report_descriptor.len() as u16;
+        [
+            0x11, 0x01, // HID 1.11
+            0x00, // not localized
+            0x01, // one class descriptor follows
+            REPORT_DESCRIPTOR,
+            len as u8,
+            (len >> 8) as u8,
+        ]
+    }
+
+    fn is_for_us(&self, req: &Request) -> bool {
+        req.recipient == Recipient::Interface && req.index == u16::from(u8::from(self.interface))
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for HidClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(self.interface, USB_CLASS_HID, self.subclass as u8, self.protocol as u8)?;
+        writer.write(HID_DESCRIPTOR, &self.hid_descriptor())?;
+        writer.endpoint(&self.endpoint)
+    }
+
+    fn reset(&mut self) {
+        self.idle = 0;
+        self.active_protocol = 1;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_for_us(&req) {
+            return;
+        }
+
+        match (req.request_type, req.request) {
+            (RequestType::Standard, Request::GET_DESCRIPTOR) => match req.descriptor_type_index() {
+                (REPORT_DESCRIPTOR, _) => xfer.accept_with_static(self.report_descriptor).ok(),
+                (HID_DESCRIPTOR, _) => xfer.accept_with(&self.hid_descriptor()).ok(),
+                _ => xfer.reject().ok(),
+            },
+            (RequestType::Class, GET_IDLE) => xfer.accept_with(&[self.idle]).ok(),
+            (RequestType::Class, GET_PROTOCOL) => xfer.accept_with(&[self.active_protocol]).ok(),
+            // reports are only sent on the interrupt endpoint
+            (RequestType::Class, GET_REPORT) => xfer.reject().ok(),
+            _ => None,
+        };
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_for_us(&req) || req.request_type != RequestType::Class {
+            return;
+        }
+
+        match req.request {
+            SET_IDLE => {
+                self.idle = (req.value >> 8) as u8;
+                xfer.accept().ok();
+            }
+            SET_PROTOCOL => {
+                self.active_protocol = req.value as u8;
+                xfer.accept().ok();
+            }
+            // output reports, e.g. a keyboard's caps lock led, aren't used by the board
+            SET_REPORT => {
+                xfer.accept().ok();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! USB HID keyboard and media keys driven by the user button and board tilt, e.g. as a presentation remote
+//!
+//! The mapping from input to keys lives in [`crate::gestures`], which doesn't need the `usb` feature,
+//! and is re-exported here.
+//! [`UsbKeyboard`] is the USB device that taps the resulting actions.
+//! It enumerates as both a keyboard and a consumer control device, so it can send either kind of action.
+//!
+//! # Example
+//!
+//! ```
+//! let mut keyboard = UsbKeyboard::new(&usb_bus);
+//! let keymap = KeyMap::presenter();
+//! let mut button = ButtonEvents::new(button);
+//! let mut tilt = TiltGestures::default();
+//! let mut tick = Periodic::new(Duration::from_millis(10));
+//! loop {
+//!     keyboard.poll();
+//!     if tick.poll() {
+//!         let now = time::now().as_millis() as u32;
+//!         let from_button = button.update(now).unwrap().and_then(Gesture::from_button);
+//!         let from_tilt = tilt.update(compass.accel_norm().unwrap());
+//!         if let Some(action) = from_button.or(from_tilt).and_then(|gesture| keymap.action(gesture)) {
+//!             keyboard.tap(action);
+//!         }
+//!     }
+//! }
+//! ```
+use usb_device::bus::UsbBusAllocator;
+use usb_device::prelude::*;
+
+use super::hid::{HidClass, Protocol, Subclass};
+use super::{UsbBusType, UsbIds};
+pub use crate::gestures::{key, media, modifier, Action, Gesture, KeyMap, TiltGestures};
+
+/// pid.codes test product ID for [`UsbKeyboard`], see [`UsbIds::test()`]
+pub const KEYBOARD_PID: u16 = 0x0003;
+
+const KEYBOARD_REPORT_ID: u8 = 1;
+const CONSUMER_REPORT_ID: u8 = 2;
+
+/// A keyboard with modifiers and up to six keys as report 1, and a consumer control as report 2
+#[rustfmt::skip]
+pub const KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xa1, 0x01,       // Collection (Application)
+    0x85, KEYBOARD_REPORT_ID, // Report ID
+    0x05, 0x07,       //   Usage Page (Keyboard)
+    0x19, 0xe0,       //   Usage Minimum (Left Control)
+    0x29, 0xe7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute), modifiers
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x01,       //   Input (Constant), reserved
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, 0xff,       //   Usage Maximum (255)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x06,       //   Report Count (6)
+    0x81, 0x00,       //   Input (Data, Array), keys
+    0xc0,             // End Collection
+    0x05, 0x0c,       // Usage Page (Consumer)
+    0x09, 0x01,       // Usage (Consumer Control)
+    0xa1, 0x01,       // Collection (Application)
+    0x85, CONSUMER_REPORT_ID, // Report ID
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2a, 0xff, 0x03, //   Usage Maximum (0x3ff)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xff, 0x03, //   Logical Maximum (0x3ff)
+    0x75, 0x10,       //   Report Size (16)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x00,       //   Input (Data, Array)
+    0xc0,             // End Collection
+];
+
+/// Longest report, the keyboard one with its ID
+pub const MAX_REPORT_LEN: usize = 9;
+/// How often the host reads a report, in milliseconds
+const POLL_MS: u8 = 10;
+
+impl Action {
+    /// Writes the report that presses the action's keys into `buffer`, returning its length
+    pub fn press_report(self, buffer: &mut [u8; MAX_REPORT_LEN]) -> usize {
+        match self {
+            Action::Key { modifiers, key } => {
+                *buffer = [KEYBOARD_REPORT_ID, modifiers, 0, key, 0, 0, 0, 0, 0];
+                MAX_REPORT_LEN
+            }
+            Action::Media(usage) => media_report(buffer, usage),
+        }
+    }
+
+    /// Writes the report that releases the action's keys into `buffer`, returning its length
+    pub fn release_report(self, buffer: &mut [u8; MAX_REPORT_LEN]) -> usize {
+        match self {
+            Action::Key { .. } => {
+                *buffer = [KEYBOARD_REPORT_ID, 0, 0, 0, 0, 0, 0, 0, 0];
+                MAX_REPORT_LEN
+            }
+            Action::Media(_) => media_report(buffer, 0),
+        }
+    }
+}
+
+fn media_report(buffer: &mut [u8; MAX_REPORT_LEN], usage: u16) -> usize {
+    buffer[..3].copy_from_slice(&[CONSUMER_REPORT_ID, usage as u8, (usage >> 8) as u8]);
+    3
+}
+
+/// Progress of the action being tapped
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Tap {
+    Idle,
+    Press(Action),
+    Release(Action),
+}
+
+/// A USB keyboard and consumer control device on the USER USB connector
+pub struct UsbKeyboard<'a> {
+    device: UsbDevice<'a, UsbBusType>,
+    hid: HidClass<'a, UsbBusType>,
+    tap: Tap,
+}
+
+impl<'a> UsbKeyboard<'a> {
+    /// Builds the HID class and the USB device around it, with the test IDs from [`UsbIds::test()`]
+    pub fn new(bus: &'a UsbBusAllocator<UsbBusType>) -> Self {
+        UsbKeyboard::with_ids(bus, UsbIds::test(KEYBOARD_PID))
+    }
+
+    /// Same as [`Self::new()`], but identifies itself to the host with `ids`
+    pub fn with_ids(bus: &'a UsbBusAllocator<UsbBusType>, ids: UsbIds) -> Self {
+        let hid = HidClass::new(
+            bus,
+            KEYBOARD_REPORT_DESCRIPTOR,
+            MAX_REPORT_LEN as u16,
+            POLL_MS,
+            Subclass::None,
+            Protocol::None,
+        );
+        let device = ids.device(bus).build();
+        UsbKeyboard { device, hid, tap: Tap::Idle }
+    }
+
+    /// Services the USB peripheral and sends the next report of a tap.
+    /// Must be called at least every 10 ms, or on every USB interrupt.
+    pub fn poll(&mut self) -> bool {
+        let polled = self.device.poll(&mut [&mut self.hid]);
+        if self.is_configured() {
+            self.send_tap();
+        }
+        polled
+    }
+
+    /// True once the host has configured the device
+    pub fn is_configured(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured
+    }
+
+    /// Presses and releases `action`'s keys over the next calls to [`Self::poll()`].
+    /// Returns false, dropping `action`, if the previous tap hasn't been sent yet.
+    pub fn tap(&mut self, action: Action) -> bool {
+        if self.tap != Tap::Idle {
+            return false;
+        }
+        self.tap = Tap::Press(action);
+        true
+    }
+
+    /// True while a tap is being sent
+    pub fn is_busy(&self) -> bool {
+        self.tap != Tap::Idle
+    }
+
+    fn send_tap(&mut self) {
+        let mut report = [0; MAX_REPORT_LEN];
+        let (len, next) = match self.tap {
+            Tap::Idle => return,
+            Tap::Press(action) => (action.press_report(&mut report), Tap::Release(action)),
+            Tap::Release(action) => (action.release_report(&mut report), Tap::Idle),
+        };
+        // the report stays pending until the host has read the previous one
+        if self.hid.push_report(&report[..len]).is_ok() {
+            self.tap = next;
+        }
+    }
+
+    /// The underlying USB device, e.g. to check its state
+    pub fn device(&mut self) -> &mut UsbDevice<'a, UsbBusType> {
+        &mut self.device
+    }
+
+    /// Releases the USB device and HID class
+    pub fn free(self) -> (UsbDevice<'a, UsbBusType>, HidClass<'a, UsbBusType>) {
+        (self.device, self.hid)
+    }
+}
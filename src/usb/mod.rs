@@ -0,0 +1,252 @@
+//! USB device support on the USER USB connector, with a CDC-ACM virtual serial port
+//!
+//! Enabled with the `usb` feature.
+//! Besides [`UsbSerial`], the board can act as a [`mouse::UsbMouse`] driven by tilting it,
+//! or a [`keyboard::UsbKeyboard`] sending keys and media keys for button presses and tilt gestures.
+//!
+//! The USER USB connector is wired to PA11 (D-) and PA12 (D+), and the board has a fixed 1.5k pull-up on D+.
+//! Because of the pull-up, the host doesn't see a reset when new firmware is flashed,
+//! so [`bus()`] pulls D+ low for a moment first to force the host to enumerate the board again.
+//!
+//! The devices enumerate with pid.codes test IDs, which are only meant for development.
+//! Give a device its own VID/PID and strings with a [`UsbIds`] and its `with_ids()` constructor.
+//!
+//! USB needs a 48 MHz clock from the PLL running off the HSE,
+//! so freeze the clocks with [`crate::clocks::ClockProfile::UsbCapable48MHz`] or [`crate::clocks::ClockProfile::Max72MHz`].
+//!
+//! # Example
+//!
+//! ```
+//! let (clocks, _) = ClockProfile::UsbCapable48MHz.freeze(rcc.cfgr, &mut flash.acr);
+//! let usb_bus = usb::bus(
+//!     device_periphs.USB,
+//!     gpioa.pa11,
+//!     gpioa.pa12,
+//!     &mut gpioa.moder,
+//!     &mut gpioa.otyper,
+//!     &mut gpioa.afrh,
+//!     clocks,
+//! );
+//! let mut serial = UsbSerial::new(&usb_bus);
+//!
+//! loop {
+//!     serial.poll();
+//!     writeln!(serial, "Hello over USB").ok();
+//! }
+//! ```
+pub mod composite;
+pub mod hid;
+pub mod keyboard;
+pub mod mouse;
+
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::NVIC;
+use stm32f3xx_hal::gpio::gpioa::{self, PA11, PA12};
+use stm32f3xx_hal::gpio::{PushPull, AF14};
+use stm32f3xx_hal::pac::{Interrupt, USB};
+use stm32f3xx_hal::prelude::*;
+use stm32f3xx_hal::rcc::Clocks;
+use stm32f3xx_hal::usb::{Peripheral, UsbBus};
+use usb_device::bus::UsbBusAllocator;
+use usb_device::prelude::*;
+use usb_device::UsbError;
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+use crate::device_info::Uid;
+
+/// The USB peripheral on the board's D- and D+ pins
+pub type UsbBusType = UsbBus<Peripheral<PA11<AF14<PushPull>>, PA12<AF14<PushPull>>>>;
+
+/// The vendor ID of [pid.codes](https://pid.codes), which hands out product IDs to open source projects
+pub const PID_CODES_VID: u16 = 0x1209;
+/// pid.codes test product ID for [`UsbSerial`]
+pub const SERIAL_PID: u16 = 0x0001;
+/// The crate rather than the chip vendor, which didn't make these devices
+pub const MANUFACTURER: &str = env!("CARGO_PKG_NAME");
+pub const PRODUCT: &str = "STM32F3DISCOVERY";
+
+/// How a USB device identifies itself to the host, for the devices' `with_ids()` constructors
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UsbIds {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+    /// Tells boards apart on the host
+    pub serial_number: &'static str,
+}
+
+impl UsbIds {
+    /// One of the pid.codes test product IDs under [`PID_CODES_VID`], with [`MANUFACTURER`], [`PRODUCT`]
+    /// and the board's [`serial_number()`]. This is what the devices' `new()` constructors use.
+    ///
+    /// Test IDs are only meant for development: a device that is handed out needs a VID/PID of its own,
+    /// e.g. one requested from pid.codes for an open source project.
+    pub fn test(product_id: u16) -> Self {
+        UsbIds {
+            vendor_id: PID_CODES_VID,
+            product_id,
+            manufacturer: MANUFACTURER,
+            product: PRODUCT,
+            serial_number: serial_number(),
+        }
+    }
+
+    /// Starts building a USB device with these IDs
+    pub(crate) fn device<'a>(&self, bus: &'a UsbBusAllocator<UsbBusType>) -> UsbDeviceBuilder<'a, UsbBusType> {
+        UsbDeviceBuilder::new(bus, UsbVidPid(self.vendor_id, self.product_id))
+            .manufacturer(self.manufacturer)
+            .product(self.product)
+            .serial_number(self.serial_number)
+    }
+}
+
+/// The serial number, once [`serial_number()`] has formatted it into [`SERIAL_DIGITS`]
+static SERIAL_NUMBER: Mutex<Cell<Option<&'static str>>> = Mutex::new(Cell::new(None));
+/// Backing storage for [`SERIAL_NUMBER`], only written while it is still `None`
+static SERIAL_DIGITS: Mutex<UnsafeCell<[u8; 24]>> = Mutex::new(UnsafeCell::new([0; 24]));
+
+/// Sets up the USB peripheral and its pins, and forces the host to enumerate the board again.
+///
+/// The allocator has to outlive the device and classes built from it,
+/// so keep it in `main`, which never returns, or in a `static` (e.g. with `cortex_m::singleton!`).
+///
+/// # Panics
+///
+/// If `clocks` can't drive USB, see [`Clocks::usbclk_valid()`].
+#[allow(clippy::too_many_arguments)]
+pub fn bus<Pa11Mode, Pa12Mode>(
+    usb: USB,
+    pa11: PA11<Pa11Mode>,
+    pa12: PA12<Pa12Mode>,
+    moder: &mut gpioa::MODER,
+    otyper: &mut gpioa::OTYPER,
+    afrh: &mut gpioa::AFRH,
+    clocks: Clocks,
+) -> UsbBusAllocator<UsbBusType> {
+    assert!(clocks.usbclk_valid(), "USB needs a 48 MHz clock from the HSE, see `ClockProfile`");
+
+    // Pulling against the board's pull-up for 10 ms looks like a disconnect to the host
+    let mut dp = pa12.into_push_pull_output(moder, otyper);
+    dp.set_low().ok();
+    cortex_m::asm::delay(clocks.sysclk().0 / 100);
+
+    let pin_dm = pa11.into_af14_push_pull(moder, otyper, afrh);
+    let pin_dp = dp.into_af14_push_pull(moder, otyper, afrh);
+    UsbBus::new(Peripheral { usb, pin_dm, pin_dp })
+}
+
+/// USB serial number unique to the board: the MCU's unique ID as 24 hex digits, see [`Uid::to_hex()`].
+///
+/// It tells boards apart on the host, e.g. through `/dev/serial/by-id` on Linux.
+pub fn serial_number() -> &'static str {
+    interrupt::free(|cs| {
+        let serial_number = SERIAL_NUMBER.borrow(cs);
+        if let Some(formatted) = serial_number.get() {
+            return formatted;
+        }
+        // Safety: Only reached once, since the cell is filled in before the critical section ends,
+        // so no other reference to the digits exists yet and the buffer is never written again.
+        let digits = unsafe { &mut *SERIAL_DIGITS.borrow(cs).get() };
+        let formatted = Uid::read().to_hex(digits);
+        serial_number.set(Some(formatted));
+        formatted
+    })
+}
+
+/// Lets the USB interrupt run, so the device's `poll()` can be called from the `USB_LP_CAN_RX0` handler
+/// instead of the main loop.
+pub fn listen() {
+    unsafe {
+        NVIC::unmask(Interrupt::USB_LP_CAN_RX0);
+    }
+}
+
+/// Stops the USB interrupt
+pub fn unlisten() {
+    NVIC::mask(Interrupt::USB_LP_CAN_RX0);
+}
+
+/// A CDC-ACM virtual serial port, which shows up as `/dev/ttyACMx` or a COM port on the host.
+///
+/// Writing is done through [`Self::write()`] or `core::fmt::Write`, so `write!` and `writeln!` work.
+pub struct UsbSerial<'a> {
+    device: UsbDevice<'a, UsbBusType>,
+    port: SerialPort<'a, UsbBusType>,
+}
+
+impl<'a> UsbSerial<'a> {
+    /// Builds the serial port class and the USB device around it, with the test IDs from [`UsbIds::test()`]
+    pub fn new(bus: &'a UsbBusAllocator<UsbBusType>) -> Self {
+        UsbSerial::with_ids(bus, UsbIds::test(SERIAL_PID))
+    }
+
+    /// Same as [`Self::new()`], but identifies itself to the host with `ids`
+    pub fn with_ids(bus: &'a UsbBusAllocator<UsbBusType>, ids: UsbIds) -> Self {
+        let port = SerialPort::new(bus);
+        let device = ids.device(bus).device_class(USB_CLASS_CDC).build();
+        UsbSerial { device, port }
+    }
+
+    /// Services the USB peripheral. Must be called at least every 10 ms, or on every USB interrupt.
+    /// Returns true if there may be new data to read.
+    pub fn poll(&mut self) -> bool {
+        self.device.poll(&mut [&mut self.port])
+    }
+
+    /// True once the host has configured the device and opened the port
+    pub fn is_connected(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured && self.port.dtr()
+    }
+
+    /// Reads the bytes received so far into `buffer`, returning how many were read
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError> {
+        match self.port.read(buffer) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Queues as much of `data` as fits for sending, returning how many bytes were queued.
+    /// The rest goes out as the host reads, on later calls to [`Self::poll()`].
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        match self.port.write(data) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Writes all of `data`, polling the device while waiting for the host to make room.
+    /// Nothing is sent while no host has the port open, so a missing cable doesn't block the program.
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), UsbError> {
+        while !data.is_empty() {
+            if !self.is_connected() {
+                return Ok(());
+            }
+            let written = self.write(data)?;
+            data = &data[written..];
+            if written == 0 {
+                self.poll();
+            }
+        }
+        Ok(())
+    }
+
+    /// The underlying USB device, e.g. to check its state
+    pub fn device(&mut self) -> &mut UsbDevice<'a, UsbBusType> {
+        &mut self.device
+    }
+
+    /// Releases the USB device and serial port class
+    pub fn free(self) -> (UsbDevice<'a, UsbBusType>, SerialPort<'a, UsbBusType>) {
+        (self.device, self.port)
+    }
+}
+
+impl<'a> fmt::Write for UsbSerial<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
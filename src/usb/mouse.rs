@@ -0,0 +1,338 @@
+//! USB HID mouse that moves the pointer as the board is tilted
+//!
+//! [`TiltMouse`] turns accelerometer readings into pointer movement:
+//! small tilts inside a dead zone are ignored so the pointer rests while the board lies flat,
+//! and beyond it the speed ramps up along a [`Curve`] for fine control near the dead zone and fast travel at full tilt.
+//! It is plain arithmetic, so it can be tuned on the host.
+//!
+//! [`UsbMouse`] is the USB device that sends the resulting [`MouseReport`]s.
+//!
+//! # Example
+//!
+//! ```
+//! let mut mouse = UsbMouse::new(&usb_bus);
+//! let mut tilt = TiltMouse::default();
+//! let mut report_timer = Periodic::new(Duration::from_millis(10));
+//! loop {
+//!     mouse.poll();
+//!     if report_timer.poll() {
+//!         let (x, y) = tilt.update(compass.accel_norm().unwrap());
+//!         let buttons = if button.is_active().unwrap() { MouseReport::LEFT } else { 0 };
+//!         mouse.report(&MouseReport { buttons, x, y, wheel: 0 }).ok();
+//!     }
+//! }
+//! ```
+use accelerometer::vector::F32x3;
+use usb_device::bus::UsbBusAllocator;
+use usb_device::prelude::*;
+use usb_device::UsbError;
+
+use super::hid::{HidClass, Protocol, Subclass};
+use super::{UsbBusType, UsbIds};
+
+/// pid.codes test product ID for [`UsbMouse`], see [`UsbIds::test()`]
+pub const MOUSE_PID: u16 = 0x0002;
+
+/// Three buttons, then relative X, Y and wheel, laid out like the boot protocol mouse report
+#[rustfmt::skip]
+pub const MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xa1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Button)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x03, //     Usage Maximum (3)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x03, //     Report Count (3)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x05, //     Report Size (5)
+    0x81, 0x01, //     Input (Constant), padding
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x09, 0x38, //     Usage (Wheel)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7f, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x03, //     Report Count (3)
+    0x81, 0x06, //     Input (Data, Variable, Relative)
+    0xc0,       //   End Collection
+    0xc0,       // End Collection
+];
+
+/// How often the host reads a report, in milliseconds
+const POLL_MS: u8 = 10;
+
+/// One movement of the mouse, relative to the previous report
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MouseReport {
+    /// Pressed buttons, a combination of [`Self::LEFT`], [`Self::RIGHT`] and [`Self::MIDDLE`]
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    pub wheel: i8,
+}
+
+impl MouseReport {
+    pub const LEFT: u8 = 1 << 0;
+    pub const RIGHT: u8 = 1 << 1;
+    pub const MIDDLE: u8 = 1 << 2;
+
+    /// The report as sent over USB, see [`MOUSE_REPORT_DESCRIPTOR`]
+    pub fn to_bytes(self) -> [u8; 4] {
+        [self.buttons, self.x as u8, self.y as u8, self.wheel as u8]
+    }
+}
+
+/// How the pointer speed grows with the tilt past the dead zone
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Curve {
+    /// Speed proportional to the tilt
+    Linear,
+    /// Speed grows with the square of the tilt, for finer control of small movements
+    Quadratic,
+    /// Speed grows with the cube of the tilt, for very fine control of small movements
+    Cubic,
+}
+
+impl Curve {
+    /// Applies the curve to `t` in `0.0..=1.0`
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::Quadratic => t * t,
+            Curve::Cubic => t * t * t,
+        }
+    }
+}
+
+/// Maps the tilt along one axis, as the gravity measured on it in g, to a pointer speed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TiltAxis {
+    /// Tilts up to this many g don't move the pointer
+    pub dead_zone: f32,
+    /// Tilt in g at which the pointer reaches `max_speed`. Larger tilts don't go any faster.
+    pub full_tilt: f32,
+    /// Pointer speed at full tilt, in counts per report. A negative speed reverses the axis.
+    pub max_speed: f32,
+    pub curve: Curve,
+}
+
+impl TiltAxis {
+    /// Pointer speed for `tilt`, in counts per report. A NaN reading doesn't move the pointer.
+    pub fn speed(&self, tilt: f32) -> f32 {
+        let magnitude = if tilt < 0.0 { -tilt } else { tilt };
+        if tilt.is_nan() || magnitude <= self.dead_zone || self.full_tilt <= self.dead_zone {
+            return 0.0;
+        }
+
+        let t = (magnitude - self.dead_zone) / (self.full_tilt - self.dead_zone);
+        let speed = self.curve.apply(if t > 1.0 { 1.0 } else { t }) * self.max_speed;
+        if tilt < 0.0 {
+            -speed
+        } else {
+            speed
+        }
+    }
+}
+
+impl Default for TiltAxis {
+    /// Ignores tilts below about 6°, reaching 20 counts per report at about 45°
+    fn default() -> Self {
+        TiltAxis { dead_zone: 0.1, full_tilt: 0.7, max_speed: 20.0, curve: Curve::Quadratic }
+    }
+}
+
+/// Turns accelerometer readings into pointer movement.
+///
+/// The pointer X axis follows the accelerometer's X axis and the pointer Y axis its Y axis.
+/// Give an axis a negative `max_speed` to reverse it.
+///
+/// Fractions of a count are carried over to the next report, so slow movements aren't lost to rounding.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TiltMouse {
+    pub x: TiltAxis,
+    pub y: TiltAxis,
+    remainder_x: f32,
+    remainder_y: f32,
+}
+
+impl TiltMouse {
+    pub fn new(x: TiltAxis, y: TiltAxis) -> Self {
+        TiltMouse { x, y, remainder_x: 0.0, remainder_y: 0.0 }
+    }
+
+    /// Pointer movement for one report, from the acceleration in g, e.g. from `Compass::accel_norm()`
+    pub fn update(&mut self, accel: F32x3) -> (i8, i8) {
+        let x = whole_counts(self.x.speed(accel.x), &mut self.remainder_x);
+        let y = whole_counts(self.y.speed(accel.y), &mut self.remainder_y);
+        (x, y)
+    }
+
+    /// Drops any carried over fractions, e.g. after the pointer was paused
+    pub fn reset(&mut self) {
+        self.remainder_x = 0.0;
+        self.remainder_y = 0.0;
+    }
+}
+
+/// Adds `speed` to `remainder` and takes out the whole counts that fit in a report
+fn whole_counts(speed: f32, remainder: &mut f32) -> i8 {
+    let total = *remainder + speed;
+    // `as` truncates toward zero and saturates at the i8 limits
+    let counts = total as i8;
+    *remainder = total - f32::from(counts);
+    // don't build up movement the report couldn't carry
+    if *remainder > 1.0 || *remainder < -1.0 {
+        *remainder = 0.0;
+    }
+    counts
+}
+
+/// A USB mouse on the USER USB connector
+pub struct UsbMouse<'a> {
+    device: UsbDevice<'a, UsbBusType>,
+    hid: HidClass<'a, UsbBusType>,
+}
+
+impl<'a> UsbMouse<'a> {
+    /// Builds the HID class and the USB device around it, with the test IDs from [`UsbIds::test()`]
+    pub fn new(bus: &'a UsbBusAllocator<UsbBusType>) -> Self {
+        UsbMouse::with_ids(bus, UsbIds::test(MOUSE_PID))
+    }
+
+    /// Same as [`Self::new()`], but identifies itself to the host with `ids`
+    pub fn with_ids(bus: &'a UsbBusAllocator<UsbBusType>, ids: UsbIds) -> Self {
+        let hid = HidClass::new(bus, MOUSE_REPORT_DESCRIPTOR, 4, POLL_MS, Subclass::Boot, Protocol::Mouse);
+        let device = ids.device(bus).build();
+        UsbMouse { device, hid }
+    }
+
+    /// Services the USB peripheral. Must be called at least every 10 ms, or on every USB interrupt.
+    pub fn poll(&mut self) -> bool {
+        self.device.poll(&mut [&mut self.hid])
+    }
+
+    /// True once the host has configured the device
+    pub fn is_configured(&self) -> bool {
+        self.device.state() == UsbDeviceState::Configured
+    }
+
+    /// Queues `report` to be read by the host.
+    /// Returns false if it wasn't queued because the previous report hasn't been read yet.
+    pub fn report(&mut self, report: &MouseReport) -> Result<bool, UsbError> {
+        match self.hid.push_report(&report.to_bytes()) {
+            Ok(_) => Ok(true),
+            Err(UsbError::WouldBlock) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The underlying USB device, e.g. to check its state
+    pub fn device(&mut self) -> &mut UsbDevice<'a, UsbBusType> {
+        &mut self.device
+    }
+
+    /// Releases the USB device and HID class
+    pub fn free(self) -> (UsbDevice<'a, UsbBusType>, HidClass<'a, UsbBusType>) {
+        (self.device, self.hid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis(curve: Curve) -> TiltAxis {
+        TiltAxis { dead_zone: 0.25, full_tilt: 0.75, max_speed: 10.0, curve }
+    }
+
+    fn accel(x: f32, y: f32) -> F32x3 {
+        F32x3::new(x, y, 1.0)
+    }
+
+    #[test]
+    fn dead_zone_doesnt_move() {
+        let axis = axis(Curve::Linear);
+        assert_eq!(axis.speed(0.0), 0.0);
+        assert_eq!(axis.speed(0.25), 0.0);
+        assert_eq!(axis.speed(-0.25), 0.0);
+        assert!(axis.speed(0.26) > 0.0);
+        assert_eq!(axis.speed(f32::NAN), 0.0);
+    }
+
+    #[test]
+    fn curves_ramp_up_from_the_dead_zone() {
+        // halfway between the dead zone and full tilt
+        assert_eq!(axis(Curve::Linear).speed(0.5), 5.0);
+        assert_eq!(axis(Curve::Quadratic).speed(0.5), 2.5);
+        assert_eq!(axis(Curve::Cubic).speed(0.5), 1.25);
+    }
+
+    #[test]
+    fn speed_saturates_at_full_tilt() {
+        for &curve in [Curve::Linear, Curve::Quadratic, Curve::Cubic].iter() {
+            let axis = axis(curve);
+            assert_eq!(axis.speed(0.75), 10.0);
+            assert_eq!(axis.speed(1.0), 10.0);
+            assert_eq!(axis.speed(-4.0), -10.0);
+            assert_eq!(axis.speed(f32::INFINITY), 10.0);
+        }
+    }
+
+    #[test]
+    fn sign_follows_the_tilt_and_max_speed() {
+        let axis = axis(Curve::Quadratic);
+        assert_eq!(axis.speed(-0.5), -axis.speed(0.5));
+
+        let reversed = TiltAxis { max_speed: -10.0, ..axis };
+        assert_eq!(reversed.speed(0.5), -2.5);
+        assert_eq!(reversed.speed(-0.5), 2.5);
+    }
+
+    #[test]
+    fn full_tilt_inside_the_dead_zone_never_moves() {
+        let axis = TiltAxis { full_tilt: 0.1, ..axis(Curve::Linear) };
+        assert_eq!(axis.speed(1.0), 0.0);
+    }
+
+    #[test]
+    fn fractions_carry_over_between_reports() {
+        let slow = TiltAxis { max_speed: 0.5, ..axis(Curve::Linear) };
+        let mut mouse = TiltMouse::new(slow, slow);
+        assert_eq!(mouse.update(accel(1.0, -1.0)), (0, 0));
+        assert_eq!(mouse.update(accel(1.0, -1.0)), (1, -1));
+        assert_eq!(mouse.update(accel(1.0, -1.0)), (0, 0));
+
+        mouse.update(accel(1.0, -1.0));
+        mouse.reset();
+        assert_eq!(mouse.update(accel(1.0, -1.0)), (0, 0));
+    }
+
+    #[test]
+    fn reports_saturate_without_building_up() {
+        let fast = TiltAxis { max_speed: 300.0, ..axis(Curve::Linear) };
+        let mut mouse = TiltMouse::new(fast, fast);
+        assert_eq!(mouse.update(accel(1.0, -1.0)), (127, -128));
+        assert_eq!(mouse.update(accel(0.0, 0.0)), (0, 0));
+    }
+
+    #[test]
+    fn nan_readings_dont_stop_the_pointer() {
+        let mut mouse = TiltMouse::new(axis(Curve::Linear), axis(Curve::Linear));
+        assert_eq!(mouse.update(accel(f32::NAN, f32::NAN)), (0, 0));
+        assert_eq!(mouse.update(accel(0.75, -0.75)), (10, -10));
+    }
+
+    #[test]
+    fn axes_are_independent() {
+        let mut mouse = TiltMouse::new(axis(Curve::Linear), axis(Curve::Linear));
+        assert_eq!(mouse.update(accel(0.75, 0.0)), (10, 0));
+        assert_eq!(mouse.update(accel(0.0, 0.75)), (0, 10));
+    }
+}
@@ -0,0 +1,129 @@
+//! Independent watchdog (IWDG) that resets the board unless it is fed in time
+//!
+//! The IWDG runs from the ~40 kHz LSI, independently of the system clock, so it still fires if the clocks fail.
+//! Once started it can't be stopped, not even by dropping the [`Watchdog`]; only a reset turns it off.
+//!
+//! By default it is frozen while the core is halted by a debugger, so sitting at a breakpoint doesn't reset the board.
+//!
+//! The LSI is only accurate to about 30-50 kHz, so the real timeout can be 20% shorter than configured.
+//! Feed the watchdog well within the timeout, e.g. at least twice per timeout.
+//!
+//! # Example
+//!
+//! ```
+//! if Watchdog::caused_last_reset() {
+//!     iprintln!(stim, "the main loop got stuck");
+//! }
+//!
+//! let mut watchdog = Watchdog::start(device_periphs.IWDG, &device_periphs.DBGMCU, WatchdogConfig::default());
+//! loop {
+//!     do_work();
+//!     watchdog.feed();
+//! }
+//! ```
+use core::time::Duration;
+use stm32f3xx_hal::pac::{DBGMCU, IWDG};
+use stm32f3xx_hal::prelude::*;
+use stm32f3xx_hal::time::duration::Milliseconds;
+use stm32f3xx_hal::watchdog::IndependentWatchDog;
+
+use crate::reset::{self, ResetCause};
+
+/// Longest timeout the IWDG supports, 4096 LSI ticks with the largest prescaler of 256
+pub const MAX_TIMEOUT: Duration = Duration::from_millis(4095 * 256 / 40);
+/// Shortest timeout [`Watchdog::start()`] sets, 10 LSI ticks with the smallest prescaler of 4.
+/// A zero timeout would load a reload value of 0, resetting the board before it could ever be fed.
+pub const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Settings for [`Watchdog::start()`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WatchdogConfig {
+    /// How long the watchdog waits for a feed, at the nominal 40 kHz LSI.
+    /// Rounded up to whole milliseconds and kept between [`MIN_TIMEOUT`] and [`MAX_TIMEOUT`].
+    pub timeout: Duration,
+    /// Stop the watchdog while a debugger has halted the core
+    pub freeze_in_debug: bool,
+}
+
+impl Default for WatchdogConfig {
+    /// A one second timeout that is frozen while debugging
+    fn default() -> Self {
+        WatchdogConfig { timeout: Duration::from_secs(1), freeze_in_debug: true }
+    }
+}
+
+/// A running independent watchdog
+pub struct Watchdog {
+    iwdg: IndependentWatchDog,
+}
+
+impl Watchdog {
+    /// Starts the watchdog. It can't be stopped again.
+    ///
+    /// The freeze setting lives in DBGMCU, which is only reset by a power-on,
+    /// so it is written every time rather than only when it is enabled.
+    pub fn start(iwdg: IWDG, dbgmcu: &DBGMCU, config: WatchdogConfig) -> Self {
+        let mut iwdg = IndependentWatchDog::new(iwdg);
+        iwdg.stop_on_debug(dbgmcu, config.freeze_in_debug);
+
+        iwdg.start(Milliseconds(timeout_millis(config.timeout)));
+        iwdg.feed();
+        Watchdog { iwdg }
+    }
+
+    /// Restarts the countdown. Must be called again within the timeout.
+    pub fn feed(&mut self) {
+        self.iwdg.feed();
+    }
+
+    /// The timeout the hardware was set to, which may be slightly shorter than configured
+    /// as the prescaler and reload registers have limited resolution.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(u64::from(self.iwdg.interval().0))
+    }
+
+    /// True if the last reset was caused by the independent watchdog, see [`reset::reset_cause()`]
+    pub fn caused_last_reset() -> bool {
+        reset::reset_cause() == ResetCause::IndependentWatchdog
+    }
+}
+
+/// `timeout` rounded up to whole milliseconds, between [`MIN_TIMEOUT`] and [`MAX_TIMEOUT`]
+fn timeout_millis(timeout: Duration) -> u32 {
+    let timeout = if timeout > MAX_TIMEOUT {
+        MAX_TIMEOUT
+    } else if timeout < MIN_TIMEOUT {
+        MIN_TIMEOUT
+    } else {
+        timeout
+    };
+    let whole_millis = Duration::from_millis(timeout.as_millis() as u64);
+    let millis = if whole_millis == timeout { whole_millis } else { whole_millis + Duration::from_millis(1) };
+    millis.as_millis() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_timeouts_are_raised_to_the_minimum() {
+        assert_eq!(timeout_millis(Duration::from_secs(0)), 1);
+        assert_eq!(timeout_millis(Duration::from_micros(1)), 1);
+        assert_eq!(timeout_millis(Duration::from_micros(999)), 1);
+        assert_eq!(timeout_millis(MIN_TIMEOUT), 1);
+    }
+
+    #[test]
+    fn timeouts_round_up_to_whole_milliseconds() {
+        assert_eq!(timeout_millis(Duration::from_micros(1_001)), 2);
+        assert_eq!(timeout_millis(Duration::from_millis(250)), 250);
+    }
+
+    #[test]
+    fn long_timeouts_are_capped() {
+        assert_eq!(timeout_millis(MAX_TIMEOUT), 26_208);
+        assert_eq!(timeout_millis(Duration::from_secs(60)), 26_208);
+        assert_eq!(timeout_millis(Duration::from_secs(u64::MAX)), 26_208);
+    }
+}